@@ -11,6 +11,9 @@ pub enum VcfParseError {
     #[error("Insufficient columns in CHROM header line")]
     NotEnoughColumnsInChromLine,
 
+    #[error("VCF columns should be tab delimited, but they seem to be delimited by {detected}")]
+    WrongDelimiter { detected: String },
+
     #[error("Invalid position value '{value}' in line: '{line}'")]
     InvalidPosition { value: String, line: String },
 
@@ -39,6 +42,30 @@ pub enum VcfParseError {
         given: usize,
     },
 
+    #[error("Record has {count} alleles, more than the maximum ({max}) in line '{line}'")]
+    TooManyAlleles {
+        count: usize,
+        max: usize,
+        line: String,
+    },
+
+    #[error("Line of {bytes} bytes longer than the maximum of {max} bytes")]
+    LineTooLong { bytes: u64, max: usize },
+
+    #[error("Record at {chrom}:{cur_pos} comes after a record at {chrom}:{prev_pos}")]
+    UnsortedRecords {
+        chrom: String,
+        prev_pos: u32,
+        cur_pos: u32,
+    },
+
+    #[error("Record at {chrom}:{pos} is more than {window} bases out of order")]
+    SortWindowExceeded {
+        chrom: String,
+        pos: u32,
+        window: u64,
+    },
+
     #[error("I/O error: {source}")]
     Io {
         #[from]
@@ -57,6 +84,9 @@ pub enum VcfParseError {
     #[error("Gzip in stdin is not supported")]
     GzipInStdinNotSupported,
 
+    #[error("Reading the last records is not supported for gzipped files")]
+    GzipTailNotSupported,
+
     #[error("VCF file should be gzipped")]
     VCFFileShouldBeGzipped,
 
@@ -66,15 +96,24 @@ pub enum VcfParseError {
     #[error("gVCF line has not enough fields to be a variant")]
     GVCFLineNotEnoughFields,
 
+    #[error("{input} is not UTF-8 text, it might be compressed or a binary file such as a BCF")]
+    NotTextInput { input: String },
+
     #[error("VCF file ended before reading the whole header")]
     BrokenHeader,
 
+    #[error("No #CHROM header line found before the variant lines or the end of the file")]
+    MissingChromHeader,
+
     #[error("Malformed header fields and sample definition line")]
     MalformedHeader,
 
     #[error("Invariant gVCF line")]
     InvariantgVCFLine,
 
+    #[error("Chromosome '{chrom}' not found in the offset index")]
+    ChromNotFoundInIndex { chrom: String },
+
     #[error("RuntimeError: {message}")]
     RuntimeError { message: String },
 }
@@ -1,40 +1,169 @@
 use crate::errors::VcfParseError;
-use crate::utils_magic::file_is_gzipped;
+use crate::utils_magic::{reader_is_gzipped, MagicByteError};
 use flate2::read::MultiGzDecoder;
-use std::collections::VecDeque;
+use roaring::RoaringBitmap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom};
 use std::path::Path;
+#[cfg(feature = "profiling")]
+use std::time::Instant;
 
 pub type VcfResult<T> = std::result::Result<T, VcfParseError>;
+pub type BoxedGVcfIter = Box<dyn Iterator<Item = VcfResult<GVcfRecord>>>;
 
 const NON_REF: &str = "<NON_REF>";
 const DEF_N_VARIANTS_IN_BUFFER: usize = 100;
+const N_FIXED_COLUMNS: usize = 8;
+const DEF_ALT_DELIMITER: char = ',';
+const TAIL_CHUNK_SIZE: u64 = 64 * 1024;
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct GVcfRecord {
     pub chrom: String,
     pub pos: u32,
     pub alleles: Vec<String>,
 }
 
-impl GVcfRecord {
-    fn from_line(line: &str) -> VcfResult<Self> {
+/// Chromosome order used to sort records by genomic coordinate.
+/// Chromosomes in the rank map go first, ordered by rank, and the rest follow in natural order:
+/// 1..22, X, Y, MT and then any other name, with or without the "chr" prefix.
+#[derive(Debug, Clone, Default)]
+pub struct ChromOrder {
+    ranks: HashMap<String, usize>,
+}
+
+impl ChromOrder {
+    pub fn new(ranks: HashMap<String, usize>) -> Self {
+        ChromOrder { ranks }
+    }
+
+    pub fn compare_chroms(&self, chrom1: &str, chrom2: &str) -> Ordering {
+        match (self.ranks.get(chrom1), self.ranks.get(chrom2)) {
+            (Some(rank1), Some(rank2)) => rank1.cmp(rank2),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => natural_chrom_key(chrom1)
+                .cmp(&natural_chrom_key(chrom2))
+                .then_with(|| chrom1.cmp(chrom2)),
+        }
+    }
+
+    /// Compares by chromosome, position, REF and ALT alleles.
+    pub fn compare(&self, record1: &GVcfRecord, record2: &GVcfRecord) -> Ordering {
+        self.compare_chroms(&record1.chrom, &record2.chrom)
+            .then(record1.pos.cmp(&record2.pos))
+            .then_with(|| record1.alleles.cmp(&record2.alleles))
+    }
+}
+
+fn natural_chrom_key(chrom: &str) -> (u8, u32, &str) {
+    let name = chrom.strip_prefix("chr").unwrap_or(chrom);
+    if let Ok(number) = name.parse::<u32>() {
+        return (0, number, "");
+    }
+    match name {
+        "X" => (1, 0, ""),
+        "Y" => (2, 0, ""),
+        "M" | "MT" => (3, 0, ""),
+        _ => (4, 0, name),
+    }
+}
+
+impl Ord for GVcfRecord {
+    /// Genomic order, using the natural chromosome order of ChromOrder.
+    fn cmp(&self, other: &Self) -> Ordering {
+        ChromOrder::default().compare(self, other)
+    }
+}
+
+impl PartialOrd for GVcfRecord {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// How the breakend joins the mate piece, following the VCF specification notation,
+/// in which t is the REF base and p the mate position.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BreakendOrientation {
+    /// t[p[: the piece extending to the right of p is joined after t
+    RightOfMateJoinedAfter,
+    /// t]p]: the reverse complement of the piece extending left of p is joined after t
+    LeftOfMateReversedJoinedAfter,
+    /// ]p]t: the piece extending to the left of p is joined before t
+    LeftOfMateJoinedBefore,
+    /// [p[t: the reverse complement of the piece extending right of p is joined before t
+    RightOfMateReversedJoinedBefore,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Breakend {
+    pub mate_chrom: String,
+    pub mate_pos: u32,
+    pub orientation: BreakendOrientation,
+}
+
+impl Breakend {
+    fn from_allele(allele: &str) -> Option<Self> {
+        let bracket = allele.chars().find(|&char| char == '[' || char == ']')?;
+        let pieces: Vec<&str> = allele.split(bracket).collect();
+        if pieces.len() != 3 {
+            return None;
+        }
+        let joined_after = match (pieces[0].is_empty(), pieces[2].is_empty()) {
+            (false, true) => true,
+            (true, false) => false,
+            _ => return None,
+        };
+        let (mate_chrom, mate_pos) = pieces[1].rsplit_once(':')?;
+        let orientation = match (joined_after, bracket) {
+            (true, '[') => BreakendOrientation::RightOfMateJoinedAfter,
+            (true, _) => BreakendOrientation::LeftOfMateReversedJoinedAfter,
+            (false, ']') => BreakendOrientation::LeftOfMateJoinedBefore,
+            (false, _) => BreakendOrientation::RightOfMateReversedJoinedBefore,
+        };
+        Some(Breakend {
+            mate_chrom: mate_chrom.to_string(),
+            mate_pos: mate_pos.parse().ok()?,
+            orientation,
+        })
+    }
+}
+
+/// Borrowed view of a variant line that does not allocate a String per field.
+/// It borrows from the line, so it has to be dropped before the line buffer is cleared to
+/// read the next line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GVcfRecordRef<'a> {
+    pub chrom: &'a str,
+    pub pos: u32,
+    pub alleles: Vec<&'a str>,
+}
+
+impl<'a> GVcfRecordRef<'a> {
+    /// Parses the CHROM, POS, REF and ALT columns. Invariant lines fail with InvariantgVCFLine.
+    pub fn from_line(line: &'a str) -> VcfResult<Self> {
+        Self::from_line_with_alt_delimiter(line, DEF_ALT_DELIMITER)
+    }
+
+    fn from_line_with_alt_delimiter(line: &'a str, alt_delimiter: char) -> VcfResult<Self> {
         let mut fields = line.splitn(6, '\t');
         let chrom = fields
             .next()
-            .ok_or_else(|| VcfParseError::GVCFLineNotEnoughFields)?;
+            .ok_or(VcfParseError::GVCFLineNotEnoughFields)?;
 
         let pos = fields
             .next()
-            .ok_or_else(|| VcfParseError::GVCFLineNotEnoughFields)?;
+            .ok_or(VcfParseError::GVCFLineNotEnoughFields)?;
         fields.next();
         let ref_allele = fields
             .next()
-            .ok_or_else(|| VcfParseError::GVCFLineNotEnoughFields)?;
+            .ok_or(VcfParseError::GVCFLineNotEnoughFields)?;
         let alt_alleles = fields
             .next()
-            .ok_or_else(|| VcfParseError::GVCFLineNotEnoughFields)?;
+            .ok_or(VcfParseError::GVCFLineNotEnoughFields)?;
 
         if alt_alleles == NON_REF {
             return Err(VcfParseError::InvariantgVCFLine);
@@ -44,96 +173,750 @@ impl GVcfRecord {
             .parse::<u32>()
             .map_err(|_| VcfParseError::GVCFLineNotEnoughFields)?;
 
-        let alleles: Vec<String> = std::iter::once(ref_allele)
-            .chain(alt_alleles.split(','))
+        let alleles: Vec<&str> = std::iter::once(ref_allele)
+            .chain(alt_alleles.split(alt_delimiter))
             .filter(|allele| allele != &NON_REF)
-            .map(str::to_string)
             .collect();
 
-        Ok(GVcfRecord {
-            chrom: chrom.to_string(),
-            pos: pos,
-            alleles: alleles,
+        Ok(GVcfRecordRef {
+            chrom,
+            pos,
+            alleles,
         })
     }
+
+    pub fn get_span(&self) -> VcfResult<(u32, u32)> {
+        span_from_allele_lens(self.pos, self.alleles.iter().map(|allele| allele.len()))
+    }
+
+    pub fn to_record(&self) -> GVcfRecord {
+        GVcfRecord {
+            chrom: self.chrom.to_string(),
+            pos: self.pos,
+            alleles: self
+                .alleles
+                .iter()
+                .map(|allele| allele.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// First and last positions covered by the longest allele.
+fn span_from_allele_lens<I: Iterator<Item = usize>>(
+    pos: u32,
+    allele_lens: I,
+) -> VcfResult<(u32, u32)> {
+    let max_allele_len = allele_lens.max().ok_or(VcfParseError::RuntimeError {
+        message: "There should be at least one allele".to_string(),
+    })?;
+    if max_allele_len <= 1 {
+        Ok((pos, pos))
+    } else {
+        Ok((pos, pos + max_allele_len as u32 - 1))
+    }
+}
+
+impl GVcfRecord {
+    fn from_line(line: &str, alt_delimiter: char) -> VcfResult<Self> {
+        GVcfRecordRef::from_line_with_alt_delimiter(line, alt_delimiter)
+            .map(|record| record.to_record())
+    }
+
+    /// First and last positions covered by the longest allele, in VCF coordinates.
+    /// Telomeric records (POS 0) start at 0, so a single base one spans (0, 0).
     pub fn get_span(self: &GVcfRecord) -> VcfResult<(u32, u32)> {
-        let max_allele_len = self.alleles.iter().map(|allele| allele.len()).max().ok_or(
-            VcfParseError::RuntimeError {
-                message: "There should be at least one allele".to_string(),
-            },
-        )?;
-        if max_allele_len == 1 {
-            Ok((self.pos, self.pos))
+        span_from_allele_lens(self.pos, self.alleles.iter().map(|allele| allele.len()))
+    }
+
+    /// Span of every ALT allele: an insertion spans its anchor base and any other allele,
+    /// deletions included, spans the REF. Symbolic alleles also span the REF, because
+    /// END and SVLEN are not parsed.
+    pub fn allele_spans(&self) -> VcfResult<Vec<(u32, u32)>> {
+        let ref_len = self.ref_allele().len();
+        if ref_len == 0 {
+            return Err(VcfParseError::InvalidAllele {
+                allele: self.ref_allele().to_string(),
+            });
+        }
+        let ref_end = self.pos + ref_len as u32 - 1;
+        Ok(self
+            .alt_alleles()
+            .iter()
+            .map(|allele| {
+                if !is_symbolic_allele(allele) && allele.len() > ref_len {
+                    (self.pos, self.pos)
+                } else {
+                    (self.pos, ref_end)
+                }
+            })
+            .collect())
+    }
+
+    pub fn n_alleles(&self) -> usize {
+        self.alleles.len()
+    }
+
+    /// REF allele, empty for a hand-built record without alleles.
+    pub(crate) fn ref_allele(&self) -> &str {
+        self.alleles.first().map(String::as_str).unwrap_or_default()
+    }
+
+    /// ALT alleles, empty if there are none.
+    pub(crate) fn alt_alleles(&self) -> &[String] {
+        self.alleles.get(1..).unwrap_or_default()
+    }
+
+    /// True for POS 0, used by breakends that join the telomere, before the first base.
+    pub fn is_telomeric(&self) -> bool {
+        self.pos == 0
+    }
+
+    /// True if every allele is a single base. Records with symbolic (or missing, ".") ALTs
+    /// are not SNPs.
+    pub fn is_snp(&self) -> bool {
+        self.ref_allele().len() == 1
+            && !self.alt_alleles().is_empty()
+            && self
+                .alt_alleles()
+                .iter()
+                .all(|allele| allele.len() == 1 && allele != "." && !is_symbolic_allele(allele))
+    }
+
+    /// True if any ALT, not being symbolic or missing ("."), has a length different from
+    /// the REF.
+    pub fn is_indel(&self) -> bool {
+        let ref_len = self.ref_allele().len();
+        self.alt_alleles()
+            .iter()
+            .any(|allele| allele != "." && !is_symbolic_allele(allele) && allele.len() != ref_len)
+    }
+
+    /// True if any ALT is symbolic (<DEL>), a breakend or the * overlapping deletion allele,
+    /// so its sequence is not given.
+    pub fn has_symbolic_allele(&self) -> bool {
+        self.alt_alleles()
+            .iter()
+            .any(|allele| is_symbolic_allele(allele))
+    }
+
+    /// True if the REF allele is repeated in the ALT list, a sign of a file with swapped
+    /// or miscoded REF and ALT columns.
+    pub fn suspicious_ref_alt(&self) -> bool {
+        self.alt_alleles()
+            .iter()
+            .any(|allele| allele == self.ref_allele())
+    }
+
+    /// Mate locus and orientation of a breakend allele. allele_idx indexes alleles, so the first
+    /// ALT is 1. Returns None for alleles that are not breakends.
+    pub fn breakend_mate(&self, allele_idx: usize) -> Option<Breakend> {
+        if allele_idx == 0 {
+            return None;
+        }
+        Breakend::from_allele(self.alleles.get(allele_idx)?)
+    }
+
+    /// REF and comma-joined ALT alleles, as in the VCF columns. Records with no ALT get ".".
+    pub fn ref_alt_strings(&self) -> (String, String) {
+        let alt = if self.alt_alleles().is_empty() {
+            ".".to_string()
         } else {
-            Ok((self.pos, self.pos + max_allele_len as u32 - 1))
+            self.alt_alleles().join(",")
+        };
+        (self.ref_allele().to_string(), alt)
+    }
+
+    /// Compact binary encoding for passing records between processes.
+    /// Little endian, length-prefixed: chrom, pos (u32), number of alleles and each allele.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        push_len_prefixed(&mut bytes, self.chrom.as_bytes());
+        bytes.extend_from_slice(&self.pos.to_le_bytes());
+        bytes.extend_from_slice(&(self.alleles.len() as u32).to_le_bytes());
+        for allele in &self.alleles {
+            push_len_prefixed(&mut bytes, allele.as_bytes());
         }
+        bytes
     }
+
+    pub fn from_bytes(bytes: &[u8]) -> VcfResult<Self> {
+        let mut cursor = bytes;
+        let chrom = take_len_prefixed_string(&mut cursor)?;
+        let pos = take_u32(&mut cursor)?;
+        let n_alleles = take_u32(&mut cursor)?;
+        if n_alleles == 0 {
+            return Err(VcfParseError::RuntimeError {
+                message: "Encoded GVcfRecord has no alleles".to_string(),
+            });
+        }
+        let alleles = (0..n_alleles)
+            .map(|_| take_len_prefixed_string(&mut cursor))
+            .collect::<VcfResult<Vec<String>>>()?;
+        if !cursor.is_empty() {
+            return Err(VcfParseError::RuntimeError {
+                message: "Trailing bytes after encoded GVcfRecord".to_string(),
+            });
+        }
+        Ok(GVcfRecord {
+            chrom,
+            pos,
+            alleles,
+        })
+    }
+}
+
+fn is_symbolic_allele(allele: &str) -> bool {
+    allele.starts_with('<') || allele.contains('[') || allele.contains(']') || allele == "*"
+}
+
+fn estimated_record_bytes(record: &GVcfRecord) -> usize {
+    record.chrom.len() + record.alleles.iter().map(String::len).sum::<usize>()
+}
+
+fn push_len_prefixed(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(field);
+}
+
+fn take_bytes<'a>(cursor: &mut &'a [u8], n_bytes: usize) -> VcfResult<&'a [u8]> {
+    if cursor.len() < n_bytes {
+        return Err(VcfParseError::RuntimeError {
+            message: "Truncated GVcfRecord bytes".to_string(),
+        });
+    }
+    let (taken, rest) = cursor.split_at(n_bytes);
+    *cursor = rest;
+    Ok(taken)
+}
+
+fn take_u32(cursor: &mut &[u8]) -> VcfResult<u32> {
+    let bytes = take_bytes(cursor, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn take_len_prefixed_string(cursor: &mut &[u8]) -> VcfResult<String> {
+    let len = take_u32(cursor)? as usize;
+    let bytes = take_bytes(cursor, len)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| VcfParseError::RuntimeError {
+        message: "Invalid UTF-8 in encoded GVcfRecord".to_string(),
+    })
+}
+
+/// Opens a plain or gzipped (including bgzipped) file as a buffered reader.
+/// The compression is detected from the magic bytes and the decompression is single threaded.
+pub fn open_vcf_reader<P: AsRef<Path>>(path: P) -> VcfResult<Box<dyn BufRead>> {
+    let mut file = File::open(&path)?;
+    let is_gzipped = match reader_is_gzipped(&mut file) {
+        Ok(is_gzipped) => is_gzipped,
+        Err(MagicByteError::InsufficientBytes { .. }) => false,
+        Err(_) => return Err(VcfParseError::MagicByteError),
+    };
+    if is_gzipped {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Byte offsets of the first line of every genomic bin in a plain, sorted, gVCF file.
+#[derive(Debug)]
+pub struct OffsetIndex {
+    bin_size: u32,
+    offsets: HashMap<String, BTreeMap<u32, u64>>,
+}
+
+impl OffsetIndex {
+    /// Offset of the closest indexed bin at or before the position.
+    /// If there is none, the first bin of the chromosome is used.
+    fn get_offset(&self, chrom: &str, pos: u32) -> Option<u64> {
+        let chrom_offsets = self.offsets.get(chrom)?;
+        chrom_offsets
+            .range(..=pos / self.bin_size)
+            .next_back()
+            .or_else(|| chrom_offsets.iter().next())
+            .map(|(_, offset)| *offset)
+    }
+}
+
+/// Scans an uncompressed gVCF once to index the byte offset of every bin of bin_size bases.
+pub fn build_line_offset_index<P: AsRef<Path>>(path: P, bin_size: u32) -> VcfResult<OffsetIndex> {
+    if bin_size == 0 {
+        return Err(VcfParseError::RuntimeError {
+            message: "The offset index bin size should be greater than 0".to_string(),
+        });
+    }
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut offsets: HashMap<String, BTreeMap<u32, u64>> = HashMap::new();
+    let mut line = String::new();
+    let mut offset: u64 = 0;
+    loop {
+        line.clear();
+        let n_bytes = reader.read_line(&mut line)?;
+        if n_bytes == 0 {
+            break;
+        }
+        if !line.starts_with('#') && !line.trim().is_empty() {
+            let mut fields = line.splitn(3, '\t');
+            let chrom = fields
+                .next()
+                .ok_or(VcfParseError::GVCFLineNotEnoughFields)?;
+            let pos = fields
+                .next()
+                .ok_or(VcfParseError::GVCFLineNotEnoughFields)?
+                .parse::<u32>()
+                .map_err(|_| VcfParseError::GVCFLineNotEnoughFields)?;
+            offsets
+                .entry(chrom.to_string())
+                .or_default()
+                .entry(pos / bin_size)
+                .or_insert(offset);
+        }
+        offset += n_bytes as u64;
+    }
+    Ok(OffsetIndex { bin_size, offsets })
+}
+
+/// Last n records of a plain gVCF, read backwards from the end of the file in chunks, so the
+/// rest of the file is not scanned. Gzipped files are not supported because they can not be
+/// read backwards. A last line without a newline is returned if it parses, and dropped if it
+/// does not, as in a file truncated in the middle of a record.
+pub fn tail_records<P: AsRef<Path>>(path: P, n: usize) -> VcfResult<Vec<GVcfRecord>> {
+    let mut file = File::open(&path)?;
+    match reader_is_gzipped(&mut file) {
+        Ok(true) => return Err(VcfParseError::GzipTailNotSupported),
+        Ok(false) | Err(MagicByteError::InsufficientBytes { .. }) => (),
+        Err(_) => return Err(VcfParseError::MagicByteError),
+    }
+
+    let mut records = Vec::new();
+    // Bytes from the start of the last read chunk up to the first unparsed line end
+    let mut pending: Vec<u8> = Vec::new();
+    // The text after the last newline, maybe a truncated line, is the first one found
+    let mut is_last_line = true;
+    let mut chunk_end = file.seek(SeekFrom::End(0))?;
+    while chunk_end > 0 && records.len() < n {
+        let chunk_start = chunk_end.saturating_sub(TAIL_CHUNK_SIZE);
+        let mut chunk = vec![0; (chunk_end - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))?;
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut pending);
+        chunk_end = chunk_start;
+
+        // The first line of the chunk might start in the previous one
+        let first_line_end = match chunk_start {
+            0 => 0,
+            _ => match chunk.iter().position(|&byte| byte == b'\n') {
+                Some(idx) => idx + 1,
+                None => {
+                    pending = chunk;
+                    continue;
+                }
+            },
+        };
+        let complete_lines = chunk.split_off(first_line_end);
+        pending = chunk;
+
+        for line in complete_lines.rsplit(|&byte| byte == b'\n') {
+            let is_truncated_line = std::mem::replace(&mut is_last_line, false);
+            let line = match std::str::from_utf8(line) {
+                Ok(line) => line.trim_end_matches('\r'),
+                Err(_) if is_truncated_line => continue,
+                Err(_) => {
+                    return Err(VcfParseError::NotTextInput {
+                        input: path.as_ref().to_string_lossy().to_string(),
+                    })
+                }
+            };
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('#') {
+                records.reverse();
+                return Ok(records);
+            }
+            match GVcfRecord::from_line(line, DEF_ALT_DELIMITER) {
+                Ok(record) => records.push(record),
+                Err(VcfParseError::InvariantgVCFLine) => continue,
+                Err(_) if is_truncated_line => continue,
+                Err(error) => return Err(error),
+            }
+            if records.len() == n {
+                break;
+            }
+        }
+    }
+    records.reverse();
+    Ok(records)
 }
 
 #[derive(Debug, PartialEq, Eq)]
 enum VcfSection {
     Header,
     Body,
+    // The header could not be parsed, so there are no records to read
+    Failed,
 }
 
 pub struct GVcfRecordIterator<B: BufRead> {
     reader: B,
     line: String,
     section: VcfSection,
-    buffer: VecDeque<GVcfRecord>,
+    // Records are stored with the byte offset at which their line starts
+    buffer: VecDeque<(u64, GVcfRecord)>,
+    // An error found while filling the buffer, returned once the records read before it are
+    pending_error: Option<VcfParseError>,
+    // Line offset of the first record taken out by drain_buffer, given to the requeued ones
+    drained_offset: Option<u64>,
+    n_bytes_read: u64,
+    line_start: u64,
+    input_description: String,
+    chrom_rename: HashMap<String, String>,
+    chr_prefix: Option<bool>,
+    max_alleles: Option<usize>,
+    uppercase_alleles: bool,
+    alt_delimiter: char,
+    max_line_bytes: Option<usize>,
+    lenient_header: bool,
+    skipped_header_lines: Vec<String>,
+    #[cfg(feature = "profiling")]
+    timing_stats: TimingStats,
+}
+
+/// Time spent reading (and decompressing) lines and parsing them, and the number of parsed
+/// records.
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    pub io_nanos: u64,
+    pub parse_nanos: u64,
+    pub records: u64,
 }
 
 impl<B: BufRead> GVcfRecordIterator<B> {
-    fn new(reader: B) -> Self {
+    fn new(reader: B, input_description: &str) -> Self {
         GVcfRecordIterator {
-            reader: reader,
+            reader,
             line: String::new(),
             section: VcfSection::Header,
             buffer: VecDeque::new(),
+            pending_error: None,
+            drained_offset: None,
+            n_bytes_read: 0,
+            line_start: 0,
+            input_description: input_description.to_string(),
+            chrom_rename: HashMap::new(),
+            chr_prefix: None,
+            max_alleles: None,
+            uppercase_alleles: false,
+            alt_delimiter: DEF_ALT_DELIMITER,
+            max_line_bytes: None,
+            lenient_header: false,
+            skipped_header_lines: Vec::new(),
+            #[cfg(feature = "profiling")]
+            timing_stats: TimingStats::default(),
+        }
+    }
+
+    /// Renames chromosomes using the given map. Unmapped chromosomes are kept as they are.
+    pub fn with_chrom_rename(mut self, chrom_rename: HashMap<String, String>) -> Self {
+        self.chrom_rename = chrom_rename;
+        self
+    }
+
+    /// Adds (true) or removes (false) the "chr" prefix of the chromosome names.
+    /// It is applied after the chrom rename map.
+    pub fn with_chr_prefix(mut self, add: bool) -> Self {
+        self.chr_prefix = Some(add);
+        self
+    }
+
+    /// Fails with TooManyAlleles for records with more than max alleles, REF included.
+    pub fn with_max_alleles(mut self, max: usize) -> Self {
+        self.max_alleles = Some(max);
+        self
+    }
+
+    /// Uppercases soft-masked REF and ALT alleles. Symbolic and breakend alleles are kept as
+    /// they are.
+    pub fn with_uppercase_alleles(mut self, uppercase: bool) -> Self {
+        self.uppercase_alleles = uppercase;
+        self
+    }
+
+    /// Character used to split the ALT column, for files that do not use the standard comma.
+    pub fn with_alt_delimiter(mut self, delimiter: char) -> Self {
+        self.alt_delimiter = delimiter;
+        self
+    }
+
+    /// Fails with LineTooLong for lines longer than max bytes, line terminator included,
+    /// instead of reading them whole into memory. By default lines have no limit.
+    pub fn with_max_line_bytes(mut self, max: usize) -> Self {
+        self.max_line_bytes = Some(max);
+        self
+    }
+
+    /// Skips the # header lines that are neither ## lines nor the #CHROM line, like stray
+    /// comments, instead of failing. Blank header lines are always skipped.
+    pub fn with_lenient_header(mut self, lenient: bool) -> Self {
+        self.lenient_header = lenient;
+        self
+    }
+
+    #[cfg(feature = "profiling")]
+    pub fn timing_stats(&self) -> TimingStats {
+        self.timing_stats
+    }
+
+    /// Header lines skipped by the lenient header mode.
+    pub fn skipped_header_lines(&self) -> &[String] {
+        &self.skipped_header_lines
+    }
+
+    fn read_line(&mut self) -> VcfResult<usize> {
+        #[cfg(feature = "profiling")]
+        let start = Instant::now();
+        let result = self.read_next_line();
+        #[cfg(feature = "profiling")]
+        {
+            self.timing_stats.io_nanos += start.elapsed().as_nanos() as u64;
+        }
+        result
+    }
+
+    fn read_next_line(&mut self) -> VcfResult<usize> {
+        self.line.clear();
+        self.line_start = self.n_bytes_read;
+        let max = match self.max_line_bytes {
+            Some(max) => max,
+            None => {
+                let n_bytes = self
+                    .reader
+                    .read_line(&mut self.line)
+                    .map_err(|error| match error.kind() {
+                        ErrorKind::InvalidData => self.not_text_input_error(),
+                        _ => VcfParseError::from(error),
+                    })?;
+                self.n_bytes_read += n_bytes as u64;
+                return Ok(n_bytes);
+            }
+        };
+
+        // At most one byte past the limit is read, so a huge line is never held in memory
+        let mut bytes = std::mem::take(&mut self.line).into_bytes();
+        let n_bytes = self.read_capped_chunk(&mut bytes, max)?;
+        if n_bytes > max {
+            // The rest of the line is discarded in chunks of the same size, so the
+            // next read starts at the following line and each long line gives one error
+            let mut line_bytes = n_bytes as u64;
+            while bytes.last() != Some(&b'\n') {
+                bytes.clear();
+                let n_bytes = self.read_capped_chunk(&mut bytes, max)?;
+                if n_bytes == 0 {
+                    break;
+                }
+                line_bytes += n_bytes as u64;
+            }
+            bytes.clear();
+            self.line = String::from_utf8(bytes).unwrap_or_default();
+            return Err(VcfParseError::LineTooLong {
+                bytes: line_bytes,
+                max,
+            });
+        }
+        self.line = String::from_utf8(bytes).map_err(|_| self.not_text_input_error())?;
+        Ok(n_bytes)
+    }
+
+    fn read_capped_chunk(&mut self, bytes: &mut Vec<u8>, max: usize) -> VcfResult<usize> {
+        let len_before = bytes.len();
+        let result = (&mut self.reader)
+            .take(max as u64 + 1)
+            .read_until(b'\n', bytes);
+        // The bytes read before an error are counted too, so current_byte_offset stays right
+        self.n_bytes_read += (bytes.len() - len_before) as u64;
+        Ok(result?)
+    }
+
+    fn not_text_input_error(&self) -> VcfParseError {
+        VcfParseError::NotTextInput {
+            input: self.input_description.clone(),
+        }
+    }
+
+    fn normalize_chrom(&self, chrom: String) -> String {
+        let mut chrom = match self.chrom_rename.get(&chrom) {
+            Some(new_chrom) => new_chrom.clone(),
+            None => chrom,
+        };
+        match self.chr_prefix {
+            Some(true) if !chrom.starts_with("chr") => {
+                chrom.insert_str(0, "chr");
+            }
+            Some(false) => {
+                if let Some(stripped) = chrom.strip_prefix("chr") {
+                    chrom = stripped.to_string();
+                }
+            }
+            _ => (),
+        }
+        chrom
+    }
+
+    fn timed_parse<T>(&mut self, parse: fn(&Self) -> VcfResult<T>) -> VcfResult<T> {
+        #[cfg(feature = "profiling")]
+        let start = Instant::now();
+        let result = parse(self);
+        #[cfg(feature = "profiling")]
+        {
+            self.timing_stats.parse_nanos += start.elapsed().as_nanos() as u64;
+            if result.is_ok() {
+                self.timing_stats.records += 1;
+            }
+        }
+        result
+    }
+
+    fn parse_line(&self) -> VcfResult<GVcfRecord> {
+        let mut record = GVcfRecord::from_line(&self.line, self.alt_delimiter)?;
+        record.chrom = self.normalize_chrom(record.chrom);
+        if self.uppercase_alleles {
+            for allele in record.alleles.iter_mut() {
+                if !is_symbolic_allele(allele) {
+                    allele.make_ascii_uppercase();
+                }
+            }
+        }
+        self.check_n_alleles(record.n_alleles())?;
+        Ok(record)
+    }
+
+    fn parse_span(&self) -> VcfResult<(String, u32, u32)> {
+        let record = GVcfRecordRef::from_line_with_alt_delimiter(&self.line, self.alt_delimiter)?;
+        self.check_n_alleles(record.alleles.len())?;
+        let (start, end) = record.get_span()?;
+        Ok((self.normalize_chrom(record.chrom.to_string()), start, end))
+    }
+
+    fn check_n_alleles(&self, n_alleles: usize) -> VcfResult<()> {
+        match self.max_alleles {
+            Some(max) if n_alleles > max => Err(VcfParseError::TooManyAlleles {
+                count: n_alleles,
+                max,
+                line: self.line.trim_end().to_string(),
+            }),
+            _ => Ok(()),
         }
     }
+    fn check_chrom_line(&self) -> VcfResult<()> {
+        if !self.line.starts_with("#CHROM") {
+            return Err(VcfParseError::MissingChromHeader);
+        }
+        if self.line.trim_end().split('\t').count() >= N_FIXED_COLUMNS {
+            return Ok(());
+        }
+        if self.line.split_whitespace().count() >= N_FIXED_COLUMNS {
+            return Err(VcfParseError::WrongDelimiter {
+                detected: "spaces".to_string(),
+            });
+        }
+        Err(VcfParseError::NotEnoughColumnsInChromLine)
+    }
     fn process_header_and_first_variant(&mut self) -> Option<VcfResult<GVcfRecord>> {
         loop {
-            if self.line.starts_with("##") {
-                self.line.clear();
-                match self.reader.read_line(&mut self.line) {
-                    Ok(0) => return Some(Err(VcfParseError::BrokenHeader)),
-                    Ok(_) => {
-                        if !self.line.starts_with("##") {
-                            break;
-                        }
-                    }
-                    Err(error) => return Some(Err(VcfParseError::from(error))),
+            let is_blank = self.line.trim().is_empty();
+            let is_meta = self.line.starts_with("##");
+            if !is_blank && !is_meta {
+                // Data lines are never skipped, so they fail as a missing #CHROM line
+                let is_stray_header_line =
+                    self.line.starts_with('#') && !self.line.starts_with("#CHROM");
+                if !self.lenient_header || !is_stray_header_line {
+                    break;
                 }
+                self.skipped_header_lines
+                    .push(self.line.trim_end().to_string());
             }
-        }
-        self.line.clear();
-        match self.reader.read_line(&mut self.line) {
-            Ok(0) => None, // EOF
-            Ok(_) => {
-                self.section = VcfSection::Body;
-                Some(GVcfRecord::from_line(&self.line))
+            match self.read_line() {
+                Ok(0) => return Some(Err(VcfParseError::MissingChromHeader)),
+                Ok(_) => (),
+                Err(error) => return Some(Err(error)),
             }
-            Err(error) => return Some(Err(VcfParseError::from(error))),
+        }
+        if let Err(error) = self.check_chrom_line() {
+            return Some(Err(error));
+        }
+        self.section = VcfSection::Body;
+        match self.read_line() {
+            Ok(0) => None, // EOF, a header without variants
+            Ok(_) => Some(self.timed_parse(Self::parse_line)),
+            Err(error) => Some(Err(error)),
         }
     }
+    /// Reads records into the buffer until it holds n_items or the file ends.
+    /// A file that ends before the #CHROM line fails with MissingChromHeader,
+    /// whereas a header with no variants just yields no records.
     pub fn fill_buffer(&mut self, n_items: usize) -> VcfResult<usize> {
+        self.fill_buffer_while(|buffer| buffer.len() < n_items)
+    }
+
+    /// Reads records into the buffer until their estimated size (chrom and allele bytes)
+    /// reaches max_bytes or the file ends. Records already in the buffer are counted.
+    /// Returns the number of records added.
+    pub fn fill_buffer_bytes(&mut self, max_bytes: usize) -> VcfResult<usize> {
+        let mut n_bytes = 0;
+        let mut n_counted = 0;
+        self.fill_buffer_while(|buffer| {
+            n_bytes += buffer
+                .range(n_counted..)
+                .map(|(_, record)| estimated_record_bytes(record))
+                .sum::<usize>();
+            n_counted = buffer.len();
+            n_bytes < max_bytes
+        })
+    }
+
+    fn fill_buffer_while<F>(&mut self, keep_reading: F) -> VcfResult<usize>
+    where
+        F: FnMut(&VecDeque<(u64, GVcfRecord)>) -> bool,
+    {
+        if self.section == VcfSection::Failed {
+            return Ok(0);
+        }
+        let result = self.read_records_while(keep_reading);
+        if result.is_err() && self.section == VcfSection::Header {
+            // A broken header can not be recovered from, the iterator ends after the error
+            self.section = VcfSection::Failed;
+        }
+        result
+    }
+
+    fn read_records_while<F>(&mut self, mut keep_reading: F) -> VcfResult<usize>
+    where
+        F: FnMut(&VecDeque<(u64, GVcfRecord)>) -> bool,
+    {
         let mut n_items_added: usize = 0;
-        while self.buffer.len() < n_items {
-            self.line.clear();
-            match self.reader.read_line(&mut self.line) {
+        while keep_reading(&self.buffer) {
+            match self.read_line() {
+                Ok(0) if self.section == VcfSection::Header => {
+                    return Err(VcfParseError::MissingChromHeader);
+                }
                 Ok(0) => break, // EOF
                 Ok(_) => {
                     if self.section == VcfSection::Header {
-                        let result = self.process_header_and_first_variant();
-                        if let Some(Ok(record)) = result {
-                            self.buffer.push_back(record);
-                            n_items_added += 1;
+                        match self.process_header_and_first_variant() {
+                            Some(Ok(record)) => {
+                                self.buffer.push_back((self.line_start, record));
+                                n_items_added += 1;
+                            }
+                            Some(Err(VcfParseError::InvariantgVCFLine)) => continue, // skip
+                            Some(Err(err)) => return Err(err),
+                            None => break,
                         }
                     } else {
-                        match GVcfRecord::from_line(&self.line) {
+                        match self.timed_parse(Self::parse_line) {
                             Ok(record) => {
-                                self.buffer.push_back(record);
+                                self.buffer.push_back((self.line_start, record));
                                 n_items_added += 1;
                             }
                             Err(VcfParseError::InvariantgVCFLine) => continue, // skip
@@ -142,7 +925,7 @@ impl<B: BufRead> GVcfRecordIterator<B> {
                     }
                 }
                 Err(err) => {
-                    return Err(VcfParseError::from(err));
+                    return Err(err);
                 }
             }
         }
@@ -150,32 +933,565 @@ impl<B: BufRead> GVcfRecordIterator<B> {
     }
 
     pub fn peek_items_in_buffer(&self) -> impl Iterator<Item = &GVcfRecord> {
-        self.buffer.iter()
+        self.buffer.iter().map(|(_, record)| record)
+    }
+
+    /// Buffered records of the chromosome whose spans overlap the start-end interval, both
+    /// included. The buffer is not consumed.
+    pub fn query_buffered(&self, chrom: &str, start: u32, end: u32) -> Vec<&GVcfRecord> {
+        self.peek_items_in_buffer()
+            .filter(|record| {
+                record.chrom == chrom
+                    && record
+                        .get_span()
+                        .is_ok_and(|(span_start, span_end)| span_start <= end && start <= span_end)
+            })
+            .collect()
+    }
+
+    /// Removes and returns the buffered records, in order. Their offset is kept until the
+    /// next push_front_records or next call.
+    pub fn drain_buffer(&mut self) -> Vec<GVcfRecord> {
+        self.drained_offset = self.buffer.front().map(|(line_start, _)| *line_start);
+        self.buffer.drain(..).map(|(_, record)| record).collect()
+    }
+
+    /// Puts records back in front of the buffer, so they are returned next in the given order.
+    /// It is meant to requeue the records of the preceding drain_buffer: they take the offset
+    /// of the first drained record, so current_byte_offset does not skip them. Without a
+    /// pending drain, or once next has been called after it, the records take the offset of
+    /// the buffer front.
+    pub fn push_front_records(&mut self, records: Vec<GVcfRecord>) {
+        let offset = match self.drained_offset.take() {
+            Some(drained_offset) => drained_offset.min(self.current_byte_offset()),
+            None => self.current_byte_offset(),
+        };
+        for record in records.into_iter().rev() {
+            self.buffer.push_front((offset, record));
+        }
+    }
+
+    /// Byte offset, in the decompressed stream, of the line of the next record to return, or of
+    /// the following line if the buffer is empty. Requeued records share the offset given by
+    /// push_front_records. It can be used to resume the parsing with from_reader_at_offset.
+    pub fn current_byte_offset(&self) -> u64 {
+        match self.buffer.front() {
+            Some((line_start, _)) => *line_start,
+            None => self.n_bytes_read,
+        }
+    }
+
+    /// Clears `out` and fills it with up to `max` records, reusing its capacity.
+    /// Returns the number of records read, 0 at EOF. A max of 0 is rejected, as it would
+    /// look like EOF. On an error `out` keeps the records read before it, and the next call
+    /// continues after the failed line.
+    pub fn read_batch(&mut self, out: &mut Vec<GVcfRecord>, max: usize) -> VcfResult<usize> {
+        if max == 0 {
+            return Err(VcfParseError::RuntimeError {
+                message: "read_batch needs a max of at least one record".to_string(),
+            });
+        }
+        out.clear();
+        while out.len() < max {
+            match self.next() {
+                Some(Ok(record)) => out.push(record),
+                Some(Err(error)) => return Err(error),
+                None => break,
+            }
+        }
+        Ok(out.len())
+    }
+
+    /// Stops after n records have been parsed. Errors are passed through and do not count.
+    pub fn take_records(mut self, n: usize) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        let mut n_records = 0;
+        std::iter::from_fn(move || {
+            if n_records >= n {
+                return None;
+            }
+            let result = self.next()?;
+            if result.is_ok() {
+                n_records += 1;
+            }
+            Some(result)
+        })
+    }
+
+    /// Pairs every record with its index, starting at 0. Only parsed records are counted,
+    /// header lines, skipped invariant lines and errors do not consume an index.
+    pub fn enumerate_records(self) -> impl Iterator<Item = VcfResult<(u64, GVcfRecord)>> {
+        let mut idx = 0;
+        self.map(move |result| {
+            let record = result?;
+            idx += 1;
+            Ok((idx - 1, record))
+        })
+    }
+
+    /// Keeps only the records in which all alleles are single bases. Errors are passed through.
+    pub fn snps_only(self) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        self.filter(|result| match result {
+            Ok(record) => record.is_snp(),
+            Err(_) => true,
+        })
+    }
+
+    /// Keeps only the records with at least one indel ALT. Errors are passed through.
+    pub fn indels_only(self) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        self.filter(|result| match result {
+            Ok(record) => record.is_indel(),
+            Err(_) => true,
+        })
+    }
+
+    /// Calls callback(previous_chrom, new_chrom) whenever a record has a different chromosome
+    /// than the one before it, so per chromosome state can be flushed. It is also called for
+    /// the first record, with an empty previous_chrom, but not at the end of the file.
+    pub fn on_chrom_change<F>(self, mut callback: F) -> impl Iterator<Item = VcfResult<GVcfRecord>>
+    where
+        F: FnMut(&str, &str),
+    {
+        let mut chrom: Option<String> = None;
+        self.inspect(move |result| {
+            if let Ok(record) = result {
+                let previous_chrom = chrom.as_deref();
+                if previous_chrom != Some(record.chrom.as_str()) {
+                    callback(previous_chrom.unwrap_or_default(), &record.chrom);
+                    chrom = Some(record.chrom.clone());
+                }
+            }
+        })
+    }
+
+    /// Drops the records with any symbolic ALT, keeping only sequence-resolved variants.
+    /// Errors are passed through.
+    pub fn exclude_symbolic(self) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        self.filter(|result| match result {
+            Ok(record) => !record.has_symbolic_allele(),
+            Err(_) => true,
+        })
+    }
+
+    /// Passes the records through, but returns UnsortedRecords for a record positioned before
+    /// the previous one of the same chromosome. The check restarts at every chromosome change.
+    pub fn assert_sorted(self) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        let mut previous: Option<(String, u32)> = None;
+        self.map(move |result| {
+            let record = result?;
+            if let Some((prev_chrom, prev_pos)) = &mut previous {
+                if *prev_chrom == record.chrom {
+                    if record.pos < *prev_pos {
+                        return Err(VcfParseError::UnsortedRecords {
+                            chrom: record.chrom,
+                            prev_pos: *prev_pos,
+                            cur_pos: record.pos,
+                        });
+                    }
+                    *prev_pos = record.pos;
+                    return Ok(record);
+                }
+            }
+            previous = Some((record.chrom.clone(), record.pos));
+            Ok(record)
+        })
+    }
+
+    /// Sorts records that are at most window bases out of order within each chromosome,
+    /// keeping in memory only the records of that window.
+    /// A record that arrives behind an already returned one fails with SortWindowExceeded.
+    pub fn sorted_within(self, window: u64) -> impl Iterator<Item = VcfResult<GVcfRecord>> {
+        SortedWithin {
+            records: self,
+            window,
+            heap: BinaryHeap::new(),
+            chrom: None,
+            max_pos: 0,
+            last_returned_pos: None,
+            next_chrom_record: None,
+            flushing: false,
+        }
+    }
+
+    /// Groups consecutive records that share the same key, returning each group once the key
+    /// changes. An error returns the group being built first and then the error.
+    pub fn group_by<K, F>(
+        mut self,
+        mut key: F,
+    ) -> impl Iterator<Item = VcfResult<(K, Vec<GVcfRecord>)>>
+    where
+        K: PartialEq,
+        F: FnMut(&GVcfRecord) -> K,
+    {
+        let mut group: Option<(K, Vec<GVcfRecord>)> = None;
+        let mut pending_error = None;
+        std::iter::from_fn(move || {
+            if let Some(error) = pending_error.take() {
+                return Some(Err(error));
+            }
+            loop {
+                match self.next() {
+                    Some(Ok(record)) => {
+                        let record_key = key(&record);
+                        match &mut group {
+                            Some((group_key, records)) if *group_key == record_key => {
+                                records.push(record)
+                            }
+                            _ => {
+                                if let Some(finished) = group.replace((record_key, vec![record])) {
+                                    return Some(Ok(finished));
+                                }
+                            }
+                        }
+                    }
+                    Some(Err(error)) => match group.take() {
+                        Some(finished) => {
+                            pending_error = Some(error);
+                            return Some(Ok(finished));
+                        }
+                        None => return Some(Err(error)),
+                    },
+                    None => return group.take().map(Ok),
+                }
+            }
+        })
+    }
+
+    /// Groups consecutive records of the same chromosome whose spans overlap, or are at most
+    /// max_gap bases apart, into clusters. Records are expected to be sorted by position.
+    /// An error returns the cluster being built first and then the error.
+    pub fn overlapping_clusters(
+        mut self,
+        max_gap: u64,
+    ) -> impl Iterator<Item = VcfResult<Vec<GVcfRecord>>> {
+        let mut cluster: Vec<GVcfRecord> = Vec::new();
+        let mut cluster_end: u64 = 0;
+        let mut pending_error = None;
+        std::iter::from_fn(move || {
+            if let Some(error) = pending_error.take() {
+                return Some(Err(error));
+            }
+            loop {
+                let result = match self.next() {
+                    Some(result) => result.and_then(|record| Ok((record.get_span()?, record))),
+                    None if cluster.is_empty() => return None,
+                    None => return Some(Ok(std::mem::take(&mut cluster))),
+                };
+                match result {
+                    Ok(((start, end), record)) => {
+                        let joins_cluster = cluster.last().is_some_and(|last| {
+                            last.chrom == record.chrom
+                                && start as u64 <= cluster_end.saturating_add(max_gap)
+                        });
+                        if joins_cluster {
+                            cluster_end = cluster_end.max(end as u64);
+                            cluster.push(record);
+                            continue;
+                        }
+                        cluster_end = end as u64;
+                        let finished = std::mem::replace(&mut cluster, vec![record]);
+                        if !finished.is_empty() {
+                            return Some(Ok(finished));
+                        }
+                    }
+                    Err(error) if cluster.is_empty() => return Some(Err(error)),
+                    Err(error) => {
+                        pending_error = Some(error);
+                        return Some(Ok(std::mem::take(&mut cluster)));
+                    }
+                }
+            }
+        })
+    }
+
+    /// Spans (chrom, start, end) of the remaining records, as given by GVcfRecord::get_span.
+    /// Once the header and the already buffered records are done, the spans are computed
+    /// from borrowed alleles, without allocating a String for each one.
+    pub fn spans_only(mut self) -> impl Iterator<Item = VcfResult<(String, u32, u32)>> {
+        std::iter::from_fn(move || {
+            if self.section != VcfSection::Body
+                || !self.buffer.is_empty()
+                || self.pending_error.is_some()
+            {
+                return Some(self.next()?.and_then(|record| {
+                    let (start, end) = record.get_span()?;
+                    Ok((record.chrom, start, end))
+                }));
+            }
+            loop {
+                match self.read_line() {
+                    Ok(0) => return None,
+                    Ok(_) => match self.timed_parse(Self::parse_span) {
+                        Err(VcfParseError::InvariantgVCFLine) => continue,
+                        result => return Some(result),
+                    },
+                    Err(error) => return Some(Err(error)),
+                }
+            }
+        })
+    }
+
+    /// Number of remaining records flagged by GVcfRecord::suspicious_ref_alt.
+    pub fn count_suspicious_ref_alt(self) -> VcfResult<u64> {
+        let mut n_suspicious = 0;
+        for record in self {
+            if record?.suspicious_ref_alt() {
+                n_suspicious += 1;
+            }
+        }
+        Ok(n_suspicious)
+    }
+
+    /// Chromosomes found in the remaining lines, invariant ones included, in first seen order.
+    /// Only the CHROM column is parsed.
+    pub fn chromosomes(mut self) -> VcfResult<Vec<String>> {
+        let mut chroms: Vec<String> = Vec::new();
+        for (_, record) in &self.buffer {
+            if !chroms.contains(&record.chrom) {
+                chroms.push(record.chrom.clone());
+            }
+        }
+        let mut seen_line_chroms = HashSet::new();
+        while self.read_line()? > 0 {
+            if self.line.starts_with('#') || self.line.trim().is_empty() {
+                continue;
+            }
+            let chrom = self.line.split('\t').next().unwrap_or_default().trim();
+            if seen_line_chroms.contains(chrom) {
+                continue;
+            }
+            seen_line_chroms.insert(chrom.to_string());
+            let chrom = self.normalize_chrom(chrom.to_string());
+            if !chroms.contains(&chrom) {
+                chroms.push(chrom);
+            }
+        }
+        Ok(chroms)
+    }
+
+    /// Minimum start and maximum end of the record spans of each chromosome, in first seen
+    /// order.
+    pub fn chrom_bounds(self) -> VcfResult<Vec<(String, (u32, u32))>> {
+        let mut bounds: Vec<(String, (u32, u32))> = Vec::new();
+        let mut chrom_idxs: HashMap<String, usize> = HashMap::new();
+        for span in self.spans_only() {
+            let (chrom, start, end) = span?;
+            match chrom_idxs.get(&chrom) {
+                Some(&idx) => {
+                    let (min_start, max_end) = &mut bounds[idx].1;
+                    *min_start = (*min_start).min(start);
+                    *max_end = (*max_end).max(end);
+                }
+                None => {
+                    chrom_idxs.insert(chrom.clone(), bounds.len());
+                    bounds.push((chrom, (start, end)));
+                }
+            }
+        }
+        Ok(bounds)
+    }
+
+    /// Positions of the remaining records of each chromosome, as compressed bitmaps for fast
+    /// membership queries.
+    pub fn position_bitmap(self) -> VcfResult<HashMap<String, RoaringBitmap>> {
+        let mut bitmaps: HashMap<String, RoaringBitmap> = HashMap::new();
+        for record in self {
+            let record = record?;
+            bitmaps.entry(record.chrom).or_default().insert(record.pos);
+        }
+        Ok(bitmaps)
+    }
+
+    /// Counts the length differences (ALT length - REF length) of all ALT alleles.
+    /// SNPs are counted as 0, symbolic and missing (".") alleles are ignored.
+    pub fn indel_length_histogram(self) -> VcfResult<BTreeMap<i64, u64>> {
+        let mut histogram = BTreeMap::new();
+        for record in self {
+            let record = record?;
+            let ref_len = record.ref_allele().len() as i64;
+            for alt_allele in record.alt_alleles() {
+                if alt_allele == "." || is_symbolic_allele(alt_allele) {
+                    continue;
+                }
+                *histogram
+                    .entry(alt_allele.len() as i64 - ref_len)
+                    .or_insert(0) += 1;
+            }
+        }
+        Ok(histogram)
+    }
+}
+
+impl<B: BufRead + 'static> GVcfRecordIterator<B> {
+    /// Erases the reader type, so iterators backed by different readers can be stored together.
+    pub fn boxed(self) -> BoxedGVcfIter {
+        Box::new(self)
     }
 }
 
 impl<R: Read> GVcfRecordIterator<BufReader<R>> {
     pub fn from_reader(reader: R) -> Self {
         let buf_reader = BufReader::new(reader);
-        GVcfRecordIterator::new(buf_reader)
+        GVcfRecordIterator::new(buf_reader, "input stream")
+    }
+}
+impl<R: Read + Seek> GVcfRecordIterator<BufReader<R>> {
+    /// Resumes the parsing of an uncompressed gVCF at an offset returned by current_byte_offset.
+    /// The header is not read again, so the offset should point to the start of a body line.
+    /// Compressed files are not supported because they can not be seeked by decompressed offset.
+    pub fn from_reader_at_offset(mut reader: R, offset: u64) -> VcfResult<Self> {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut iterator = GVcfRecordIterator::new(BufReader::new(reader), "input stream");
+        iterator.section = VcfSection::Body;
+        iterator.n_bytes_read = offset;
+        Ok(iterator)
+    }
+
+    /// Jumps to the start of the index bin that contains the position, so the following records
+    /// can start a few positions before the requested one.
+    pub fn seek_with_index(&mut self, index: &OffsetIndex, chrom: &str, pos: u32) -> VcfResult<()> {
+        let offset =
+            index
+                .get_offset(chrom, pos)
+                .ok_or_else(|| VcfParseError::ChromNotFoundInIndex {
+                    chrom: chrom.to_string(),
+                })?;
+        self.seek_body(offset)
+    }
+
+    /// Jumps to the first line that starts at or after the offset, so an offset in the middle
+    /// of a line is moved forward to the start of the next one. The offset should be past
+    /// the header.
+    pub fn seek_to_line_offset(&mut self, offset: u64) -> VcfResult<()> {
+        if offset == 0 {
+            return self.seek_body(0);
+        }
+        // Reading from the previous byte consumes just its newline at a line start
+        self.seek_body(offset - 1)?;
+        self.read_line()?;
+        Ok(())
+    }
+
+    fn seek_body(&mut self, offset: u64) -> VcfResult<()> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.buffer.clear();
+        self.pending_error = None;
+        self.drained_offset = None;
+        self.section = VcfSection::Body;
+        self.n_bytes_read = offset;
+        Ok(())
     }
 }
 impl<R: Read> GVcfRecordIterator<BufReader<MultiGzDecoder<R>>> {
     pub fn from_gzip_reader(reader: R) -> Self {
         let gz_decoder = MultiGzDecoder::new(reader);
         let buf_reader = BufReader::new(gz_decoder);
-        GVcfRecordIterator::new(buf_reader)
+        GVcfRecordIterator::new(buf_reader, "gzip stream")
     }
 }
-impl GVcfRecordIterator<BufReader<MultiGzDecoder<File>>> {
-    pub fn from_gzip_path<P: AsRef<Path>>(path: P) -> VcfResult<Self> {
-        if !file_is_gzipped(&path).map_err(|_| VcfParseError::MagicByteError)? {
+impl<R: Read + Seek> GVcfRecordIterator<BufReader<MultiGzDecoder<R>>> {
+    // The magic bytes are checked on the handle used for decompression, so the file is
+    // opened once
+    fn from_gzip_path_with_opener<P, F>(path: P, open: F) -> VcfResult<Self>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(&Path) -> std::io::Result<R>,
+    {
+        let mut file = open(path.as_ref())?;
+        if !reader_is_gzipped(&mut file).map_err(|_| VcfParseError::MagicByteError)? {
             return Err(VcfParseError::VCFFileShouldBeGzipped);
         }
-        let file = File::open(&path)?;
         let gz_decoder = MultiGzDecoder::new(file);
         let buf_reader = BufReader::new(gz_decoder);
-        Ok(GVcfRecordIterator::new(buf_reader))
+        Ok(GVcfRecordIterator::new(
+            buf_reader,
+            &path.as_ref().to_string_lossy(),
+        ))
+    }
+}
+impl GVcfRecordIterator<BufReader<MultiGzDecoder<File>>> {
+    /// A missing or unreadable file fails with an Io error.
+    pub fn from_gzip_path<P: AsRef<Path>>(path: P) -> VcfResult<Self> {
+        Self::from_gzip_path_with_opener(path, |path| File::open(path))
+    }
+}
+
+struct SortedWithin<I> {
+    records: I,
+    window: u64,
+    heap: BinaryHeap<Reverse<GVcfRecord>>,
+    chrom: Option<String>,
+    max_pos: u32,
+    last_returned_pos: Option<u32>,
+    // First record of the next chromosome, waiting until the current one is flushed
+    next_chrom_record: Option<GVcfRecord>,
+    flushing: bool,
+}
+
+impl<I: Iterator<Item = VcfResult<GVcfRecord>>> SortedWithin<I> {
+    fn push_record(&mut self, record: GVcfRecord) -> VcfResult<()> {
+        if self.chrom.as_ref() != Some(&record.chrom) {
+            self.chrom = Some(record.chrom.clone());
+            self.max_pos = 0;
+            self.last_returned_pos = None;
+        }
+        if let Some(last_returned_pos) = self.last_returned_pos {
+            if record.pos < last_returned_pos {
+                return Err(VcfParseError::SortWindowExceeded {
+                    chrom: record.chrom,
+                    pos: record.pos,
+                    window: self.window,
+                });
+            }
+        }
+        self.max_pos = self.max_pos.max(record.pos);
+        self.heap.push(Reverse(record));
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = VcfResult<GVcfRecord>>> Iterator for SortedWithin<I> {
+    type Item = VcfResult<GVcfRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(Reverse(first)) = self.heap.peek() {
+                if self.flushing
+                    || (first.pos as u64).saturating_add(self.window) <= self.max_pos as u64
+                {
+                    let Reverse(record) = self.heap.pop()?;
+                    self.last_returned_pos = Some(record.pos);
+                    return Some(Ok(record));
+                }
+            } else if self.flushing {
+                self.flushing = false;
+                match self.next_chrom_record.take() {
+                    Some(record) => {
+                        if let Err(error) = self.push_record(record) {
+                            return Some(Err(error));
+                        }
+                    }
+                    None => return None, // EOF
+                }
+                continue;
+            }
+
+            match self.records.next() {
+                None => self.flushing = true,
+                Some(Err(error)) => return Some(Err(error)),
+                Some(Ok(record)) => {
+                    if self
+                        .chrom
+                        .as_ref()
+                        .is_some_and(|chrom| chrom != &record.chrom)
+                    {
+                        self.next_chrom_record = Some(record);
+                        self.flushing = true;
+                    } else if let Err(error) = self.push_record(record) {
+                        return Some(Err(error));
+                    }
+                }
+            }
+        }
     }
 }
 
@@ -183,22 +1499,61 @@ impl<R: BufRead> Iterator for GVcfRecordIterator<R> {
     type Item = VcfResult<GVcfRecord>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.buffer.len() == 0 {
+        // Reading past the drained records makes their offset stale
+        self.drained_offset = None;
+        if self.buffer.is_empty() {
+            if let Some(error) = self.pending_error.take() {
+                return Some(Err(error));
+            }
             match self.fill_buffer(DEF_N_VARIANTS_IN_BUFFER) {
-                Err(error) => return Some(Err(error)),
-                Ok(n_items_added) => match n_items_added {
-                    0 => return None,
-                    _ => (),
-                },
+                Err(error) if self.buffer.is_empty() => return Some(Err(error)),
+                // The records read before the error go first
+                Err(error) => self.pending_error = Some(error),
+                Ok(0) => return None,
+                Ok(_) => (),
             }
         }
 
-        if let Some(variant) = self.buffer.pop_front() {
-            return Some(Ok(variant));
+        if let Some((_, variant)) = self.buffer.pop_front() {
+            Some(Ok(variant))
         } else {
-            return Some(Err(VcfParseError::RuntimeError {
+            Some(Err(VcfParseError::RuntimeError {
                 message: "The buffer should contain something".to_string(),
-            }));
+            }))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn test_gzip_path_opened_once() {
+        let path = std::env::temp_dir().join(format!(
+            "gvcfparser_{}_gzip_path_opened_once.g.vcf.gz",
+            std::process::id()
+        ));
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+        encoder.write_all(gvcf.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let mut n_opens = 0;
+        let records = GVcfRecordIterator::from_gzip_path_with_opener(&path, |path| {
+            n_opens += 1;
+            File::open(path)
+        })
+        .unwrap();
+        let poss: Vec<u32> = records.map(|record| record.unwrap().pos).collect();
+        assert_eq!(poss, vec![10]);
+        assert_eq!(n_opens, 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
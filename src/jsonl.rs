@@ -0,0 +1,51 @@
+use crate::gvcf_parser::{GVcfRecord, VcfResult};
+use std::io::Write;
+
+fn push_json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for char in value.chars() {
+        match char {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            char if char.is_control() => json.push_str(&format!("\\u{:04x}", char as u32)),
+            char => json.push(char),
+        }
+    }
+    json.push('"');
+}
+
+/// JSON object with the chrom, pos, ref and alt (a list) of the record.
+pub fn record_to_json(record: &GVcfRecord) -> String {
+    let mut json = String::from("{\"chrom\":");
+    push_json_string(&mut json, &record.chrom);
+    json.push_str(&format!(",\"pos\":{},\"ref\":", record.pos));
+    push_json_string(&mut json, record.ref_allele());
+    json.push_str(",\"alt\":[");
+    for (idx, allele) in record.alt_alleles().iter().enumerate() {
+        if idx > 0 {
+            json.push(',');
+        }
+        push_json_string(&mut json, allele);
+    }
+    json.push_str("]}");
+    json
+}
+
+/// Writes one JSON object per record and line (newline delimited JSON).
+/// Returns the number of records written.
+pub fn write_jsonl<I, W>(records: I, mut writer: W) -> VcfResult<usize>
+where
+    I: Iterator<Item = VcfResult<GVcfRecord>>,
+    W: Write,
+{
+    let mut n_records = 0;
+    for record in records {
+        writeln!(writer, "{}", record_to_json(&record?))?;
+        n_records += 1;
+    }
+    writer.flush()?;
+    Ok(n_records)
+}
@@ -1,3 +1,4 @@
 pub mod errors;
 pub mod gvcf_parser;
+pub mod jsonl;
 mod utils_magic;
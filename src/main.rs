@@ -1,12 +1,15 @@
-use clap::Parser;
+use clap::builder::RangedU64ValueParser;
+use clap::{ArgGroup, Parser, ValueEnum};
 use polars::prelude::ParquetWriter;
 use polars::prelude::*;
 use std::fs::File;
+use std::io::BufWriter;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
-use gvcfparser::errors::VcfParseError;
-use gvcfparser::gvcf_parser::{GVcfRecord, GVcfRecordIterator, VcfResult};
+use gvcfparser::gvcf_parser::{GVcfRecordIterator, VcfResult};
+use gvcfparser::jsonl::write_jsonl;
 
 /// Extract variant regions from a gVCF and save them to a Parquet file.
 #[derive(Parser, Debug)]
@@ -14,63 +17,216 @@ use gvcfparser::gvcf_parser::{GVcfRecord, GVcfRecordIterator, VcfResult};
 #[command(author = "Jose Blanca")]
 #[command(version = "0.1.0")]
 #[command(about = "Extracts variant spans from a .g.vcf.gz and stores them in a Parquet file.", long_about = None)]
+#[command(group(ArgGroup::new("input_source").required(true).args(["input", "inputs"])))]
 struct Args {
     /// Input .g.vcf.gz path
     #[arg(short, long)]
-    input: PathBuf,
+    input: Option<PathBuf>,
 
-    /// Output Parquet file path
+    /// Directory with .g.vcf.gz files, processed in parallel
+    #[arg(long)]
+    inputs: Option<PathBuf>,
+
+    /// Output file path, or output directory when using --inputs
     #[arg(short, long)]
     output: PathBuf,
+
+    /// Number of files processed at the same time when using --inputs
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    jobs: usize,
+
+    /// Number of variant spans kept in memory and written as one Parquet row group
+    #[arg(
+        long,
+        default_value_t = DEF_ROW_GROUP_SIZE,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    row_group_size: usize,
+
+    /// Output format: Parquet variant spans or JSON lines variant records
+    #[arg(long, value_enum, default_value_t = OutputFormat::Parquet)]
+    format: OutputFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Parquet,
+    Jsonl,
 }
 
-pub fn save_var_regions_as_parquet<I, P>(iterator: I, output_path: P) -> PolarsResult<()>
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+const GVCF_EXTENSION: &str = ".g.vcf.gz";
+const DEF_ROW_GROUP_SIZE: usize = 512 * 512;
+
+fn spans_to_dataframe(
+    chroms: &mut Vec<String>,
+    starts: &mut Vec<i64>,
+    ends: &mut Vec<i64>,
+) -> PolarsResult<DataFrame> {
+    DataFrame::new(vec![
+        Series::new("chrom".into(), std::mem::take(chroms)).into(),
+        Series::new("start".into(), std::mem::take(starts)).into(),
+        Series::new("end".into(), std::mem::take(ends)).into(),
+    ])
+}
+
+/// Writes the spans in row groups of row_group_size, so the memory used does not depend on
+/// the number of variants.
+pub fn save_var_regions_as_parquet<I, P>(
+    spans: I,
+    output_path: P,
+    row_group_size: usize,
+) -> PolarsResult<()>
 where
-    I: Iterator<Item = VcfResult<GVcfRecord>>,
+    I: Iterator<Item = VcfResult<(String, u32, u32)>>,
     P: AsRef<Path>,
 {
+    let schema = Schema::from_iter([
+        Field::new("chrom".into(), DataType::String),
+        Field::new("start".into(), DataType::Int64),
+        Field::new("end".into(), DataType::Int64),
+    ]);
+    let file = File::create(output_path)?;
+    let mut writer = ParquetWriter::new(file).batched(&schema)?;
+
     let mut chroms = Vec::new();
     let mut starts = Vec::new();
     let mut ends = Vec::new();
 
-    for rec in iterator {
-        let record = match rec {
-            Ok(record) => record,
-            Err(VcfParseError::InvariantgVCFLine) => continue,
-            Err(err) => return Err(PolarsError::ComputeError(format!("{:?}", err).into())),
-        };
-
-        match record.get_span() {
-            Ok((start, end)) => {
-                chroms.push(record.chrom);
+    for span in spans {
+        match span {
+            Ok((chrom, start, end)) => {
+                chroms.push(chrom);
                 starts.push(start as i64); // Polars uses i64 for integer columns
                 ends.push(end as i64);
             }
             Err(err) => return Err(PolarsError::ComputeError(format!("{:?}", err).into())),
         }
-    }
 
-    let mut df = DataFrame::new(vec![
-        Series::new("chrom".into(), chroms).into(),
-        Series::new("start".into(), starts).into(),
-        Series::new("end".into(), ends).into(),
-    ])?;
-
-    let file = File::create(output_path)?;
-    ParquetWriter::new(file).finish(&mut df)?;
+        if chroms.len() >= row_group_size {
+            writer.write_batch(&spans_to_dataframe(&mut chroms, &mut starts, &mut ends)?)?;
+        }
+    }
+    if !chroms.is_empty() {
+        writer.write_batch(&spans_to_dataframe(&mut chroms, &mut starts, &mut ends)?)?;
+    }
+    writer.finish()?;
     Ok(())
 }
 
-fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+fn find_gvcfs_in_dir(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_gvcf = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(GVCF_EXTENSION));
+        if path.is_file() && is_gvcf {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
 
-    let parser = GVcfRecordIterator::from_gzip_path(&args.input)?;
-    save_var_regions_as_parquet(parser, &args.output)?;
+fn get_output_path(input_path: &Path, output_dir: &Path, format: OutputFormat) -> PathBuf {
+    let file_name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let stem = file_name.strip_suffix(GVCF_EXTENSION).unwrap_or(&file_name);
+    output_dir.join(format!("{}.{}", stem, format.extension()))
+}
 
+fn save_gvcf_var_regions(
+    input_path: &Path,
+    output_path: &Path,
+    format: OutputFormat,
+    row_group_size: usize,
+) -> anyhow::Result<()> {
+    let parser = GVcfRecordIterator::from_gzip_path(input_path)?;
+    match format {
+        OutputFormat::Parquet => {
+            save_var_regions_as_parquet(parser.spans_only(), output_path, row_group_size)?
+        }
+        OutputFormat::Jsonl => {
+            write_jsonl(parser, BufWriter::new(File::create(output_path)?))?;
+        }
+    }
     println!(
-        "Wrote variant spans from {} to {}",
-        args.input.display(),
-        args.output.display()
+        "Wrote variants from {} to {}",
+        input_path.display(),
+        output_path.display()
     );
     Ok(())
 }
+
+fn save_dir_var_regions(
+    input_dir: &Path,
+    output_dir: &Path,
+    n_jobs: usize,
+    format: OutputFormat,
+    row_group_size: usize,
+) -> anyhow::Result<()> {
+    let input_paths = find_gvcfs_in_dir(input_dir)?;
+    std::fs::create_dir_all(output_dir)?;
+
+    let pending = Mutex::new(input_paths.iter());
+    let errors = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..n_jobs {
+            scope.spawn(|| loop {
+                let input_path = match pending.lock().unwrap().next() {
+                    Some(input_path) => input_path,
+                    None => break,
+                };
+                let output_path = get_output_path(input_path, output_dir, format);
+                if let Err(error) =
+                    save_gvcf_var_regions(input_path, &output_path, format, row_group_size)
+                {
+                    errors
+                        .lock()
+                        .unwrap()
+                        .push(format!("{}: {}", input_path.display(), error));
+                }
+            });
+        }
+    });
+
+    let errors = errors.into_inner().unwrap();
+    if !errors.is_empty() {
+        anyhow::bail!("Failed to process some gVCFs:\n{}", errors.join("\n"));
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    match (&args.input, &args.inputs) {
+        (Some(input), _) => {
+            save_gvcf_var_regions(input, &args.output, args.format, args.row_group_size)
+        }
+        (None, Some(input_dir)) => save_dir_var_regions(
+            input_dir,
+            &args.output,
+            args.jobs,
+            args.format,
+            args.row_group_size,
+        ),
+        (None, None) => unreachable!("clap requires one of --input and --inputs"),
+    }
+}
@@ -1,6 +1,4 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,11 +6,8 @@ pub enum MagicByteError {
     #[error("Insufficient bytes: got {got}, need at least {need}")]
     InsufficientBytes { got: usize, need: usize },
 
-    #[error("There was a problem opening the file: {path}")]
-    ProblemOpeningFile { path: String },
-
-    #[error("There was a problem opening reading the buffer for the file: {path}")]
-    ProblemFillingBuffer { path: String },
+    #[error("There was a problem reading the first bytes of the input")]
+    ProblemReadingBytes,
 }
 
 pub fn are_gzipped_magic_bytes(first_bytes: &[u8]) -> Result<bool, MagicByteError> {
@@ -25,21 +20,65 @@ pub fn are_gzipped_magic_bytes(first_bytes: &[u8]) -> Result<bool, MagicByteErro
     Ok(first_bytes[0] == 0x1f && first_bytes[1] == 0x8b)
 }
 
-fn read_first_bytes<P: AsRef<Path>>(path: &P, num_bytes: usize) -> Result<Vec<u8>, MagicByteError> {
-    let file = File::open(path).map_err(|_| MagicByteError::ProblemOpeningFile {
-        path: path.as_ref().to_string_lossy().to_string(),
-    })?;
-    let mut buf_reader = BufReader::new(file);
-
-    let buffer = buf_reader
-        .fill_buf()
-        .map_err(|_| MagicByteError::ProblemFillingBuffer {
-            path: path.as_ref().to_string_lossy().to_string(),
-        })?;
-    Ok(buffer[..num_bytes.min(buffer.len())].to_vec())
+/// Reads the first bytes and seeks back to where the reader was, so the same handle
+/// can be used afterwards.
+fn read_first_bytes<R: Read + Seek>(reader: &mut R, num_bytes: usize) -> std::io::Result<Vec<u8>> {
+    let start = reader.stream_position()?;
+    let mut buffer = Vec::with_capacity(num_bytes);
+    reader
+        .by_ref()
+        .take(num_bytes as u64)
+        .read_to_end(&mut buffer)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(buffer)
 }
 
-pub fn file_is_gzipped<P: AsRef<Path>>(path: &P) -> Result<bool, MagicByteError> {
-    let first_bytes = read_first_bytes(path, 4)?;
+/// Checks the gzip magic bytes without consuming them.
+pub fn reader_is_gzipped<R: Read + Seek>(reader: &mut R) -> Result<bool, MagicByteError> {
+    let first_bytes =
+        read_first_bytes(reader, 4).map_err(|_| MagicByteError::ProblemReadingBytes)?;
     are_gzipped_magic_bytes(&first_bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Counts the bytes read, to check that the magic bytes are really read and then rewound
+    struct CountingReader<R> {
+        inner: R,
+        n_bytes_read: usize,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n_bytes = self.inner.read(buf)?;
+            self.n_bytes_read += n_bytes;
+            Ok(n_bytes)
+        }
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_reader_is_gzipped_restores_position() {
+        let mut reader = CountingReader {
+            inner: Cursor::new(vec![0x1f, 0x8b, 0x08, 0x00, 0x01, 0x02]),
+            n_bytes_read: 0,
+        };
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        assert!(reader_is_gzipped(&mut reader).unwrap());
+        assert_eq!(reader.n_bytes_read, 4);
+        assert_eq!(reader.stream_position().unwrap(), 0);
+
+        // The check starts, and ends, at the current position
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        assert!(!reader_is_gzipped(&mut reader).unwrap());
+        assert_eq!(reader.stream_position().unwrap(), 2);
+    }
+}
@@ -0,0 +1,168 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use polars::prelude::*;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CLI_BIN: &str = env!("CARGO_BIN_EXE_save_var_regions_as_parquet");
+
+const SAMPLE_GVCF: &str = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+20\t14370\t.\tG\t<NON_REF>\t29\tPASS\t.\tGT\t0/0
+20\t17330\t.\tT\tA,<NON_REF>\t.\tq10\t.\tGT\t0/1
+20\t17331\t.\tA\tG,T,<NON_REF>\t67\tPASS\t.\tGT\t1/2
+20\t17333\t.\tGTC\tG,GTCT,<NON_REF>\t50\tPASS\t.\tGT\t0/1
+";
+
+fn create_test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gvcfparser_{}_{}", name, std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_gzipped(path: &Path, content: &str) {
+    let mut encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+    encoder.write_all(content.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+}
+
+fn count_parquet_rows(path: &Path) -> usize {
+    let file = File::open(path).expect("Problem opening parquet file");
+    ParquetReader::new(file).finish().unwrap().height()
+}
+
+#[test]
+fn test_cli_inputs_dir() {
+    let dir = create_test_dir("cli_inputs_dir");
+    let input_dir = dir.join("gvcfs");
+    let output_dir = dir.join("parquets");
+    std::fs::create_dir_all(&input_dir).unwrap();
+    write_gzipped(&input_dir.join("sample1.g.vcf.gz"), SAMPLE_GVCF);
+    write_gzipped(&input_dir.join("sample2.g.vcf.gz"), SAMPLE_GVCF);
+    std::fs::write(input_dir.join("notes.txt"), "not a gVCF").unwrap();
+
+    let output = Command::new(CLI_BIN)
+        .arg("--inputs")
+        .arg(&input_dir)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--jobs")
+        .arg("2")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    assert_eq!(count_parquet_rows(&output_dir.join("sample1.parquet")), 3);
+    assert_eq!(count_parquet_rows(&output_dir.join("sample2.parquet")), 3);
+    assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 2);
+
+    let output = Command::new(CLI_BIN)
+        .arg("--inputs")
+        .arg(&input_dir)
+        .arg("--output")
+        .arg(&output_dir)
+        .arg("--jobs")
+        .arg("0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // One of --input and --inputs, but not both, is required
+    let output = Command::new(CLI_BIN)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let output = Command::new(CLI_BIN)
+        .arg("--input")
+        .arg(input_dir.join("sample1.g.vcf.gz"))
+        .arg("--inputs")
+        .arg(&input_dir)
+        .arg("--output")
+        .arg(&output_dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_row_group_size() {
+    let dir = create_test_dir("cli_row_group_size");
+    let input_path = dir.join("large.g.vcf.gz");
+    let output_path = dir.join("large.parquet");
+    let n_variants = 10_000;
+    let mut gvcf = String::from("##fileformat=VCFv4.2\n");
+    gvcf.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001\n");
+    for pos in 1..=n_variants {
+        gvcf.push_str(&format!(
+            "1\t{}\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1\n",
+            pos
+        ));
+    }
+    write_gzipped(&input_path, &gvcf);
+
+    let output = Command::new(CLI_BIN)
+        .arg("--input")
+        .arg(&input_path)
+        .arg("--output")
+        .arg(&output_path)
+        .arg("--row-group-size")
+        .arg("1000")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(count_parquet_rows(&output_path), n_variants);
+
+    let output = Command::new(CLI_BIN)
+        .arg("--input")
+        .arg(&input_path)
+        .arg("--output")
+        .arg(&output_path)
+        .arg("--row-group-size")
+        .arg("0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_cli_jsonl_format() {
+    let dir = create_test_dir("cli_jsonl_format");
+    let input_path = dir.join("sample.g.vcf.gz");
+    let output_path = dir.join("sample.jsonl");
+    write_gzipped(&input_path, SAMPLE_GVCF);
+
+    let output = Command::new(CLI_BIN)
+        .arg("--input")
+        .arg(&input_path)
+        .arg("--output")
+        .arg(&output_path)
+        .arg("--format")
+        .arg("jsonl")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let jsonl = std::fs::read_to_string(&output_path).unwrap();
+    let records: Vec<serde_json::Value> = jsonl
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Every line should be a JSON object"))
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(
+        records[2],
+        serde_json::json!({"chrom": "20", "pos": 17333, "ref": "GTC", "alt": ["G", "GTCT"]})
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
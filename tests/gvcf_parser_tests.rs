@@ -1,9 +1,17 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use gvcfparser::{
     errors::VcfParseError,
-    gvcf_parser::{GVcfRecord, GVcfRecordIterator},
+    gvcf_parser::{
+        build_line_offset_index, open_vcf_reader, tail_records, BoxedGVcfIter, Breakend,
+        BreakendOrientation, ChromOrder, GVcfRecord, GVcfRecordIterator, GVcfRecordRef,
+    },
+    jsonl::{record_to_json, write_jsonl},
 };
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::path::PathBuf;
 
 const SAMPLE_GVCF: &str = "##
 ##FORMAT=<ID=HQ,Number=2,Type=Integer,Description=\"Haplotype Quality\">
@@ -15,6 +23,12 @@ const SAMPLE_GVCF: &str = "##
 20\t17333\t.\tGTC\tG,GTCT,<NON_REF>\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t0/1:35:4\t0/2:17:2\t1/1:40:3
 20\t17334\t.\tGTC\tG,GTCT,<NON_REF>\t50\tPASS\tNS=3;DP=9;AA=G\tGT:GQ:DP\t.:35:4\t0/2:17:2\t./1:40:3";
 
+fn write_tmp_file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("gvcfparser_{}_{}", std::process::id(), name));
+    std::fs::write(&path, content).expect("Problem writing temporary file");
+    path
+}
+
 #[test]
 fn test_gvcf_parsing() {
     let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
@@ -34,6 +48,72 @@ fn test_gvcf_parsing() {
     assert_eq!(n_variants, 4);
 }
 
+#[test]
+fn test_empty_and_header_only_inputs() {
+    // A header error is returned once and then the iterator ends
+    let mut records = GVcfRecordIterator::from_reader("".as_bytes());
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::MissingChromHeader))
+    ));
+    assert!(records.next().is_none());
+    assert_eq!(
+        GVcfRecordIterator::from_reader("".as_bytes())
+            .take(5)
+            .count(),
+        1
+    );
+
+    let mut records = GVcfRecordIterator::from_reader("##fileformat=VCFv4.2\n".as_bytes());
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::MissingChromHeader))
+    ));
+    assert!(records.next().is_none());
+
+    let gvcf = SAMPLE_GVCF.replacen("#CHROM\tPOS\tID", "#CHROM POS ID", 1);
+    let gvcf = gvcf.replacen(
+        "REF\tALT\tQUAL\tFILTER\tINFO",
+        "REF ALT QUAL FILTER INFO",
+        1,
+    );
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::WrongDelimiter { .. }))
+    ));
+    assert!(records.next().is_none());
+    let n_ok = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .snps_only()
+        .filter(|record| record.is_ok())
+        .count();
+    assert_eq!(n_ok, 0);
+    let spans: Vec<_> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .spans_only()
+        .collect();
+    assert_eq!(spans.len(), 1);
+
+    let header: String = SAMPLE_GVCF.lines().take(3).collect::<Vec<_>>().join("\n");
+    let mut records = GVcfRecordIterator::from_reader(header.as_bytes());
+    assert!(records.next().is_none());
+    assert!(records.next().is_none());
+}
+
+#[test]
+fn test_variant_before_chrom_line() {
+    let gvcf: String = SAMPLE_GVCF
+        .lines()
+        .filter(|line| !line.starts_with("#CHROM"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::MissingChromHeader))
+    ));
+    assert!(records.next().is_none());
+}
+
 #[test]
 fn test_buffer() {
     let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
@@ -75,6 +155,930 @@ fn test_buffer2() {
     }
 }
 
+#[test]
+fn test_read_batch() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let mut var_iterator = GVcfRecordIterator::from_reader(reader);
+    let mut batch = Vec::with_capacity(2);
+    let mut n_variants = 0;
+    let mut n_batches = 0;
+    loop {
+        let n_read = var_iterator.read_batch(&mut batch, 2).unwrap();
+        if n_read == 0 {
+            break;
+        }
+        assert_eq!(batch.len(), n_read);
+        n_variants += n_read;
+        n_batches += 1;
+    }
+    assert_eq!(n_variants, 4);
+    assert_eq!(n_batches, 2);
+    assert!(batch.is_empty());
+
+    let mut var_iterator = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    assert!(matches!(
+        var_iterator.read_batch(&mut batch, 0),
+        Err(VcfParseError::RuntimeError { .. })
+    ));
+
+    // The records before a failed line stay in the batch
+    let gvcf = SAMPLE_GVCF.replace("20\t17333\t", "20\tbad\t");
+    let mut var_iterator = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert!(var_iterator.read_batch(&mut batch, 10).is_err());
+    let poss: Vec<u32> = batch.iter().map(|record| record.pos).collect();
+    assert_eq!(poss, vec![17330, 17331]);
+    assert_eq!(var_iterator.read_batch(&mut batch, 10).unwrap(), 1);
+    assert_eq!(batch[0].pos, 17334);
+}
+
+#[test]
+fn test_chrom_rename() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let chrom_rename = HashMap::from([("20".to_string(), "chr20".to_string())]);
+    let records = GVcfRecordIterator::from_reader(reader).with_chrom_rename(chrom_rename);
+    let chroms: Vec<String> = records.map(|record| record.unwrap().chrom).collect();
+    assert_eq!(chroms, vec!["chr20"; 4]);
+
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let mut records = GVcfRecordIterator::from_reader(reader).with_chr_prefix(true);
+    assert_eq!(records.next().unwrap().unwrap().chrom, "chr20");
+
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let chrom_rename = HashMap::from([("20".to_string(), "chr20".to_string())]);
+    let mut records = GVcfRecordIterator::from_reader(reader)
+        .with_chrom_rename(chrom_rename)
+        .with_chr_prefix(false);
+    assert_eq!(records.next().unwrap().unwrap().chrom, "20");
+}
+
+#[test]
+fn test_space_delimited_chrom_line() {
+    let gvcf = SAMPLE_GVCF
+        .lines()
+        .map(|line| {
+            if line.starts_with("#CHROM") {
+                line.replace('\t', " ")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    let reader = BufReader::new(gvcf.as_bytes());
+    let mut records = GVcfRecordIterator::from_reader(reader);
+    match records.next() {
+        Some(Err(VcfParseError::WrongDelimiter { detected })) => assert_eq!(detected, "spaces"),
+        other => panic!("Expected a WrongDelimiter error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_resume_from_byte_offset() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let mut var_iterator = GVcfRecordIterator::from_reader(reader);
+    assert_eq!(var_iterator.next().unwrap().unwrap().pos, 17330);
+    assert_eq!(var_iterator.next().unwrap().unwrap().pos, 17331);
+    let offset = var_iterator.current_byte_offset();
+
+    let reader = Cursor::new(SAMPLE_GVCF.as_bytes());
+    let resumed = GVcfRecordIterator::from_reader_at_offset(reader, offset).unwrap();
+    let poss: Vec<u32> = resumed.map(|record| record.unwrap().pos).collect();
+    assert_eq!(poss, vec![17333, 17334]);
+}
+
+#[test]
+fn test_binary_input() {
+    let bytes: Vec<u8> = vec![b'B', b'C', b'F', 0x02, 0x02, 0xff, 0xfe, 0x00, b'\n'];
+    let mut records = GVcfRecordIterator::from_reader(bytes.as_slice());
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::NotTextInput { .. }))
+    ));
+}
+
+#[test]
+fn test_indel_length_histogram() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let histogram = GVcfRecordIterator::from_reader(reader)
+        .indel_length_histogram()
+        .unwrap();
+    let expected: Vec<(i64, u64)> = vec![(-2, 2), (0, 3), (1, 2)];
+    assert_eq!(histogram.into_iter().collect::<Vec<_>>(), expected);
+
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tGTC\t.\t.\t.\t.\tGT\t0/0
+1\t20\t.\tG\t.\t.\t.\t.\tGT\t0/0
+1\t30\t.\tG\tGT,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let histogram = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .indel_length_histogram()
+        .unwrap();
+    assert_eq!(histogram.into_iter().collect::<Vec<_>>(), vec![(1, 1)]);
+}
+
+#[test]
+fn test_seek_with_offset_index() {
+    // Blank lines, as a trailing empty one, are skipped
+    let path = write_tmp_file("offset_index.g.vcf", &format!("{}\n\n", SAMPLE_GVCF));
+    let index = build_line_offset_index(&path, 1).unwrap();
+
+    let mut var_iterator = GVcfRecordIterator::from_reader(File::open(&path).unwrap());
+    var_iterator.seek_with_index(&index, "20", 17333).unwrap();
+    assert_eq!(var_iterator.next().unwrap().unwrap().pos, 17333);
+
+    var_iterator.seek_with_index(&index, "20", 17331).unwrap();
+    assert_eq!(var_iterator.next().unwrap().unwrap().pos, 17331);
+
+    assert!(matches!(
+        var_iterator.seek_with_index(&index, "21", 100),
+        Err(VcfParseError::ChromNotFoundInIndex { .. })
+    ));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_chromosomes() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+2\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t20\t.\tA\t<NON_REF>\t.\t.\t.\tGT\t0/0
+1\t10\t.\tA\t<NON_REF>\t.\t.\t.\tGT\t0/0
+1\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+X\t5\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t30\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let chroms = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .chromosomes()
+        .unwrap();
+    assert_eq!(chroms, vec!["2", "1", "X"]);
+
+    // Blank lines are not chromosomes
+    let gvcf_with_blank_lines = format!("{}\n  \n\r\n", gvcf.replace("X\t5", "\nX\t5"));
+    let chroms = GVcfRecordIterator::from_reader(gvcf_with_blank_lines.as_bytes())
+        .chromosomes()
+        .unwrap();
+    assert_eq!(chroms, vec!["2", "1", "X"]);
+}
+
+#[test]
+fn test_take_records() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(reader)
+        .take_records(2)
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![17330, 17331]);
+}
+
+#[test]
+fn test_enumerate_records() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let idxs_and_poss: Vec<(u64, u32)> = GVcfRecordIterator::from_reader(reader)
+        .enumerate_records()
+        .map(|result| {
+            let (idx, record) = result.unwrap();
+            (idx, record.pos)
+        })
+        .collect();
+    assert_eq!(
+        idxs_and_poss,
+        vec![(0, 17330), (1, 17331), (2, 17333), (3, 17334)]
+    );
+}
+
+#[test]
+fn test_snps_and_indels_only() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(reader)
+        .snps_only()
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![17330, 17331]);
+
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(reader)
+        .indels_only()
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![17333, 17334]);
+
+    let mixed = GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 10,
+        alleles: vec!["A".to_string(), "G".to_string(), "AT".to_string()],
+    };
+    assert!(!mixed.is_snp());
+    assert!(mixed.is_indel());
+
+    let snp = |alleles: &[&str]| GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 10,
+        alleles: alleles.iter().map(|allele| allele.to_string()).collect(),
+    };
+    assert!(snp(&["A", "G"]).is_snp());
+    assert!(!snp(&["A", "*"]).is_snp());
+    assert!(!snp(&["A", "G", "*"]).is_snp());
+    assert!(!snp(&["A", "."]).is_snp());
+
+    // A missing ALT is not an indel
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tGTC\t.\t.\t.\t.\tGT\t0/0
+1\t20\t.\tGTC\tG,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .indels_only()
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![20]);
+}
+
+#[test]
+fn test_max_alleles() {
+    let alt_alleles: Vec<String> = (1..=500).map(|n_bases| "A".repeat(n_bases + 1)).collect();
+    let gvcf = format!(
+        "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\t{},<NON_REF>\t.\t.\t.\tGT\t0/1
+",
+        alt_alleles.join(",")
+    );
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes()).with_max_alleles(100);
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::TooManyAlleles {
+            count: 501,
+            max: 100,
+            ..
+        }))
+    ));
+
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert_eq!(records.next().unwrap().unwrap().n_alleles(), 501);
+}
+
+#[test]
+fn test_sorted_within() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t30\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t40\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t5\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t3\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let records: Vec<(String, u32)> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .sorted_within(20)
+        .map(|record| {
+            let record = record.unwrap();
+            (record.chrom, record.pos)
+        })
+        .collect();
+    let expected = [
+        ("1", 10),
+        ("1", 20),
+        ("1", 30),
+        ("1", 40),
+        ("2", 3),
+        ("2", 5),
+    ];
+    let expected: Vec<(String, u32)> = expected
+        .iter()
+        .map(|(chrom, pos)| (chrom.to_string(), *pos))
+        .collect();
+    assert_eq!(records, expected);
+
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes()).sorted_within(5);
+    assert_eq!(records.next().unwrap().unwrap().pos, 10);
+    assert_eq!(records.next().unwrap().unwrap().pos, 30);
+    assert!(matches!(
+        records.next(),
+        Some(Err(VcfParseError::SortWindowExceeded { pos: 20, .. }))
+    ));
+
+    // A window as large as the chromosome sorts it whole
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .sorted_within(u64::MAX)
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![10, 20, 30, 40, 3, 5]);
+}
+
+#[test]
+fn test_suspicious_ref_alt() {
+    let gvcf = SAMPLE_GVCF.replace("\tA\tG,T,<NON_REF>\t", "\tA\tG,A,<NON_REF>\t");
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert!(!records.next().unwrap().unwrap().suspicious_ref_alt());
+    assert!(records.next().unwrap().unwrap().suspicious_ref_alt());
+
+    let records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert_eq!(records.count_suspicious_ref_alt().unwrap(), 1);
+}
+
+#[test]
+fn test_uppercase_alleles() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tgtc\tg,Gtct,<DEL>,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t20\t.\ta\tc[chr2:300[\t.\t.\t.\tGT\t0/1
+";
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes()).with_uppercase_alleles(true);
+    assert_eq!(
+        records.next().unwrap().unwrap().alleles,
+        vec!["GTC", "G", "GTCT", "<DEL>"]
+    );
+    assert_eq!(
+        records.next().unwrap().unwrap().alleles,
+        vec!["A", "c[chr2:300["]
+    );
+
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert_eq!(
+        records.next().unwrap().unwrap().alleles,
+        vec!["gtc", "g", "Gtct", "<DEL>"]
+    );
+}
+
+#[test]
+fn test_write_jsonl() {
+    let records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes()).take_records(2);
+    let mut jsonl = Vec::new();
+    assert_eq!(write_jsonl(records, &mut jsonl).unwrap(), 2);
+    assert_eq!(
+        String::from_utf8(jsonl).unwrap(),
+        "{\"chrom\":\"20\",\"pos\":17330,\"ref\":\"T\",\"alt\":[\"A\"]}\n\
+         {\"chrom\":\"20\",\"pos\":17331,\"ref\":\"A\",\"alt\":[\"G\",\"T\"]}\n"
+    );
+
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+chr\"1\\\t10\t.\tA\t<DEL>,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let mut jsonl = Vec::new();
+    write_jsonl(GVcfRecordIterator::from_reader(gvcf.as_bytes()), &mut jsonl).unwrap();
+    assert_eq!(
+        String::from_utf8(jsonl.clone()).unwrap(),
+        "{\"chrom\":\"chr\\\"1\\\\\",\"pos\":10,\"ref\":\"A\",\"alt\":[\"<DEL>\"]}\n"
+    );
+    let json: serde_json::Value = serde_json::from_slice(&jsonl).unwrap();
+    assert_eq!(json["chrom"], "chr\"1\\");
+
+    // The escaped strings are read back unchanged by a JSON parser
+    let record = GVcfRecord {
+        chrom: "chr\"1\\\t\r\n\u{1}ñ".to_string(),
+        pos: 10,
+        alleles: vec!["A".to_string(), "<DEL>".to_string()],
+    };
+    let json: serde_json::Value = serde_json::from_str(&record_to_json(&record)).unwrap();
+    assert_eq!(json["chrom"], record.chrom.as_str());
+    assert_eq!(json["pos"], 10);
+    assert_eq!(json["ref"], "A");
+    assert_eq!(json["alt"], serde_json::json!(["<DEL>"]));
+}
+
+#[test]
+fn test_group_by() {
+    let groups: Vec<(String, Vec<GVcfRecord>)> =
+        GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+            .group_by(|record| record.chrom.clone())
+            .collect::<Result<_, _>>()
+            .unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].0, "20");
+    assert_eq!(groups[0].1.len(), 4);
+
+    let groups: Vec<(bool, Vec<u32>)> = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .group_by(|record| record.is_indel())
+        .map(|group| {
+            let (is_indel, records) = group.unwrap();
+            (is_indel, records.iter().map(|record| record.pos).collect())
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        vec![(false, vec![17330, 17331]), (true, vec![17333, 17334])]
+    );
+
+    // The error is found in the same buffer fill as the record before it
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t20\t.\tA\tC,G,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t5\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let mut groups = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .with_max_alleles(2)
+        .group_by(|record| record.chrom.clone());
+    let (chrom, records) = groups.next().unwrap().unwrap();
+    assert_eq!(
+        (chrom.as_str(), records[0].pos, records.len()),
+        ("1", 10, 1)
+    );
+    assert!(matches!(
+        groups.next().unwrap(),
+        Err(VcfParseError::TooManyAlleles { .. })
+    ));
+    let (chrom, records) = groups.next().unwrap().unwrap();
+    assert_eq!((chrom.as_str(), records[0].pos, records.len()), ("2", 5, 1));
+    assert!(groups.next().is_none());
+}
+
+#[test]
+fn test_telomeric_record() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+13\t0\t.\tN\tA,<NON_REF>\t.\t.\t.\tGT\t0/1
+13\t0\t.\tNAC\tN,<NON_REF>\t.\t.\t.\tGT\t0/1
+13\t1\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let records: Vec<GVcfRecord> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert!(records[0].is_telomeric());
+    assert_eq!(records[0].get_span().unwrap(), (0, 0));
+    assert!(records[1].is_telomeric());
+    assert_eq!(records[1].get_span().unwrap(), (0, 2));
+    assert!(!records[2].is_telomeric());
+    assert_eq!(records[2].get_span().unwrap(), (1, 1));
+}
+
+#[test]
+fn test_fill_buffer_bytes() {
+    // Estimated sizes of the sample records: 4, 5, 10 and 10 bytes
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    assert_eq!(records.fill_buffer_bytes(8).unwrap(), 2);
+    assert_eq!(records.fill_buffer_bytes(8).unwrap(), 0);
+    assert_eq!(records.fill_buffer_bytes(10).unwrap(), 1);
+    assert_eq!(records.fill_buffer_bytes(1000).unwrap(), 1);
+    assert_eq!(records.peek_items_in_buffer().count(), 4);
+}
+
+#[test]
+fn test_overlapping_clusters() {
+    let cluster_positions = |max_gap: u64| -> Vec<Vec<u32>> {
+        GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+            .overlapping_clusters(max_gap)
+            .map(|cluster| cluster.unwrap().iter().map(|record| record.pos).collect())
+            .collect()
+    };
+    // Spans: (17330, 17330), (17331, 17331), (17333, 17336), (17334, 17337)
+    assert_eq!(
+        cluster_positions(0),
+        vec![vec![17330], vec![17331], vec![17333, 17334]]
+    );
+    assert_eq!(
+        cluster_positions(1),
+        vec![vec![17330, 17331], vec![17333, 17334]]
+    );
+    assert_eq!(cluster_positions(2), vec![vec![17330, 17331, 17333, 17334]]);
+    assert_eq!(
+        cluster_positions(u64::MAX),
+        vec![vec![17330, 17331, 17333, 17334]]
+    );
+
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tACGT\tA,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t11\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let n_clusters = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .overlapping_clusters(10)
+        .count();
+    assert_eq!(n_clusters, 2);
+}
+
+#[test]
+fn test_drain_and_push_front_records() {
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    let first = records.next().unwrap().unwrap();
+    let offset = records.current_byte_offset();
+    let drained = records.drain_buffer();
+    assert_eq!(drained.len(), 3);
+    assert_eq!(records.peek_items_in_buffer().count(), 0);
+
+    // A checkpoint taken after the requeue still resumes at the drained records
+    records.push_front_records(drained);
+    assert_eq!(records.current_byte_offset(), offset);
+    let resumed = GVcfRecordIterator::from_reader_at_offset(Cursor::new(SAMPLE_GVCF), offset)
+        .unwrap()
+        .map(|record| record.unwrap().pos)
+        .collect::<Vec<u32>>();
+    assert_eq!(resumed, vec![17331, 17333, 17334]);
+
+    records.push_front_records(vec![first]);
+    let poss: Vec<u32> = records.map(|record| record.unwrap().pos).collect();
+    assert_eq!(poss, vec![17330, 17331, 17333, 17334]);
+
+    // The drained offset is forgotten once the iterator reads past it, or after an empty drain
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    let first = records.next().unwrap().unwrap();
+    records.drain_buffer();
+    assert!(records.next().is_none());
+    let end_offset = records.current_byte_offset();
+    records.push_front_records(vec![first]);
+    assert_eq!(records.current_byte_offset(), end_offset);
+
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    let first = records.next().unwrap().unwrap();
+    records.drain_buffer();
+    assert!(records.drain_buffer().is_empty());
+    records.push_front_records(vec![first]);
+    assert_eq!(records.current_byte_offset(), end_offset);
+}
+
+#[test]
+fn test_record_ref() {
+    let line = "20\t17333\t.\tGTC\tG,GTCT,<NON_REF>\t50\tPASS\tNS=3\tGT\t0/1";
+    let record = GVcfRecordRef::from_line(line).unwrap();
+    assert_eq!(record.chrom, "20");
+    assert_eq!(record.pos, 17333);
+    assert_eq!(record.alleles, vec!["GTC", "G", "GTCT"]);
+    assert_eq!(record.get_span().unwrap(), (17333, 17336));
+
+    let owned = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .nth(2)
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.to_record(), owned);
+
+    assert!(matches!(
+        GVcfRecordRef::from_line("20\t14370\t.\tG\t<NON_REF>\t29\tPASS\t.\tGT\t0/0"),
+        Err(VcfParseError::InvariantgVCFLine)
+    ));
+}
+
+#[test]
+fn test_spans_only() {
+    let expected_spans: Vec<(String, u32, u32)> =
+        GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+            .map(|record| {
+                let record = record.unwrap();
+                let (start, end) = record.get_span().unwrap();
+                (record.chrom, start, end)
+            })
+            .collect();
+    let spans: Vec<(String, u32, u32)> = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .spans_only()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(spans, expected_spans);
+
+    // Past the first buffer the spans are parsed straight from the lines
+    let mut gvcf = String::from("##fileformat=VCFv4.2\n");
+    gvcf.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001\n");
+    for pos in 1..=150 {
+        gvcf.push_str(&format!(
+            "1\t{}\t.\tAC\tA,<NON_REF>\t.\t.\t.\tGT\t0/1\n",
+            pos * 10
+        ));
+        gvcf.push_str(&format!(
+            "1\t{}\t.\tA\t<NON_REF>\t.\t.\t.\tGT\t0/0\n",
+            pos * 10 + 5
+        ));
+    }
+    let spans: Vec<(String, u32, u32)> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .with_chr_prefix(true)
+        .spans_only()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(spans.len(), 150);
+    assert_eq!(spans[149], ("chr1".to_string(), 1500, 1501));
+}
+
+#[test]
+fn test_alt_delimiter() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t5\t.\tA\t<NON_REF>\t.\t.\t.\tGT\t0/0
+1\t10\t.\tGTC\tG;GTCT;<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let records: Vec<GVcfRecord> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .with_alt_delimiter(';')
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].alleles, vec!["GTC", "G", "GTCT"]);
+
+    let spans: Vec<(String, u32, u32)> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .with_alt_delimiter(';')
+        .spans_only()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(spans, vec![("1".to_string(), 10, 13)]);
+}
+
+#[test]
+fn test_chrom_bounds() {
+    let bounds = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .chrom_bounds()
+        .unwrap();
+    // The GTCT allele at 17334 ends at 17337
+    assert_eq!(bounds, vec![("20".to_string(), (17330, 17337))]);
+
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+2\t50\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t30\t.\tACG\tA,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let bounds = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .chrom_bounds()
+        .unwrap();
+    assert_eq!(
+        bounds,
+        vec![("2".to_string(), (10, 50)), ("1".to_string(), (30, 32))]
+    );
+}
+
+#[test]
+fn test_seek_to_line_offset() {
+    let path = write_tmp_file("seek_to_line_offset.g.vcf", SAMPLE_GVCF);
+    let line_start = SAMPLE_GVCF.find("20\t17331").unwrap() as u64;
+    let mut records = GVcfRecordIterator::from_reader(File::open(&path).unwrap());
+
+    records.seek_to_line_offset(line_start).unwrap();
+    assert_eq!(records.next().unwrap().unwrap().pos, 17331);
+
+    // A mid-line offset moves to the next line, the invariant 17332 one, which is skipped
+    records.seek_to_line_offset(line_start + 3).unwrap();
+    assert_eq!(records.next().unwrap().unwrap().pos, 17333);
+    assert_eq!(records.count(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_position_bitmap() {
+    let bitmaps = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .position_bitmap()
+        .unwrap();
+    assert_eq!(bitmaps.len(), 1);
+    let positions: Vec<u32> = bitmaps["20"].iter().collect();
+    assert_eq!(positions, vec![17330, 17331, 17333, 17334]);
+    assert!(!bitmaps["20"].contains(14370));
+}
+
+#[test]
+fn test_on_chrom_change() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let mut changes = Vec::new();
+    let n_records = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .on_chrom_change(|previous, new| changes.push((previous.to_string(), new.to_string())))
+        .count();
+    assert_eq!(n_records, 4);
+    assert_eq!(
+        changes,
+        vec![
+            ("".to_string(), "1".to_string()),
+            ("1".to_string(), "2".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_tail_records() {
+    let path = write_tmp_file("tail_records.g.vcf", SAMPLE_GVCF);
+    let poss = |n: usize| -> Vec<u32> {
+        tail_records(&path, n)
+            .unwrap()
+            .iter()
+            .map(|record| record.pos)
+            .collect()
+    };
+    assert_eq!(poss(2), vec![17333, 17334]);
+    assert_eq!(poss(3), vec![17331, 17333, 17334]);
+    assert_eq!(poss(10), vec![17330, 17331, 17333, 17334]);
+    assert_eq!(poss(0), Vec::<u32>::new());
+    std::fs::remove_file(&path).unwrap();
+
+    // A file truncated in the middle of a record ends in an incomplete line that is dropped
+    let truncated_gvcf = format!("{}\n20\t17335\t.\tGT", SAMPLE_GVCF);
+    let path = write_tmp_file("tail_records_truncated.g.vcf", &truncated_gvcf);
+    let records = tail_records(&path, 2).unwrap();
+    assert_eq!((records[0].pos, records[1].pos), (17333, 17334));
+    std::fs::remove_file(&path).unwrap();
+
+    // Lines that cross the boundaries of the read chunks
+    let mut gvcf = String::from("##fileformat=VCFv4.2\n");
+    gvcf.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001\n");
+    for pos in 1..=20_000 {
+        gvcf.push_str(&format!(
+            "1\t{}\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1\n",
+            pos
+        ));
+    }
+    let path = write_tmp_file("tail_records_large.g.vcf", &gvcf);
+    let records = tail_records(&path, 15_000).unwrap();
+    assert_eq!(records.len(), 15_000);
+    assert_eq!(records[0].pos, 5_001);
+    assert_eq!(records[14_999].pos, 20_000);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        tail_records("tests/data/sample.g.vcf.gz", 2),
+        Err(VcfParseError::GzipTailNotSupported)
+    ));
+}
+
+#[test]
+fn test_exclude_symbolic() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<DEL>,<NON_REF>\t.\t.\t.\tGT\t0/2
+1\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t30\t.\tA\tA[2:300[,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t40\t.\tA\t*,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let has_symbolic: Vec<bool> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .map(|record| record.unwrap().has_symbolic_allele())
+        .collect();
+    assert_eq!(has_symbolic, vec![true, false, true, true]);
+
+    let poss: Vec<u32> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .exclude_symbolic()
+        .map(|record| record.unwrap().pos)
+        .collect();
+    assert_eq!(poss, vec![20]);
+}
+
+#[test]
+fn test_max_line_bytes() {
+    let mut gvcf = String::from("##fileformat=VCFv4.2\n");
+    gvcf.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001\n");
+    gvcf.push_str("1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1\n");
+    gvcf.push_str(&format!(
+        "1\t20\t.\tA\tC,<NON_REF>\t.\t.\t{}\n",
+        "A".repeat(1_000_000)
+    ));
+    gvcf.push_str("1\t30\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1\n");
+
+    let results: Vec<_> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .with_max_line_bytes(1024)
+        .collect();
+    // The rest of the long line is skipped, so it gives one error and the next
+    // record is still read
+    let n_errors = results
+        .iter()
+        .filter(|result| {
+            matches!(
+                result,
+                Err(VcfParseError::LineTooLong {
+                    bytes: 1_000_026,
+                    max: 1024
+                })
+            )
+        })
+        .count();
+    assert_eq!(n_errors, 1);
+    let positions: Vec<u32> = results
+        .iter()
+        .filter_map(|result| result.as_ref().ok())
+        .map(|record| record.pos)
+        .collect();
+    assert_eq!(positions, [10, 30]);
+
+    let records = GVcfRecordIterator::from_reader(gvcf.as_bytes()).with_max_line_bytes(2_000_000);
+    assert_eq!(records.filter(|record| record.is_ok()).count(), 3);
+}
+
+#[test]
+fn test_lenient_header() {
+    let gvcf = "##fileformat=VCFv4.2
+
+# converted by some tool
+##contig=<ID=1>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert!(matches!(
+        records.next().unwrap(),
+        Err(VcfParseError::MissingChromHeader)
+    ));
+
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes()).with_lenient_header(true);
+    assert_eq!(records.next().unwrap().unwrap().pos, 10);
+    assert!(records.next().is_none());
+    assert_eq!(records.skipped_header_lines(), ["# converted by some tool"]);
+
+    // Without a #CHROM line the data lines are not skipped as header lines
+    let no_chrom_gvcf: String = gvcf
+        .lines()
+        .filter(|line| !line.starts_with("#CHROM"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let mut records =
+        GVcfRecordIterator::from_reader(no_chrom_gvcf.as_bytes()).with_lenient_header(true);
+    assert!(matches!(
+        records.next().unwrap(),
+        Err(VcfParseError::MissingChromHeader)
+    ));
+    assert_eq!(records.skipped_header_lines(), ["# converted by some tool"]);
+
+    // Blank lines are skipped even without the lenient mode
+    let gvcf = gvcf.replace("# converted by some tool\n", "");
+    let mut records = GVcfRecordIterator::from_reader(gvcf.as_bytes());
+    assert_eq!(records.next().unwrap().unwrap().pos, 10);
+    assert!(records.skipped_header_lines().is_empty());
+}
+
+#[test]
+fn test_allele_spans() {
+    let records: Vec<GVcfRecord> = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records[1].allele_spans().unwrap(), vec![(17331, 17331); 2]);
+    // GTC -> G is a deletion and GTC -> GTCT an insertion
+    assert_eq!(
+        records[2].allele_spans().unwrap(),
+        vec![(17333, 17335), (17333, 17333)]
+    );
+
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tAC\tGT,<DEL>,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let record = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(record.allele_spans().unwrap(), vec![(10, 11), (10, 11)]);
+}
+
+#[test]
+fn test_query_buffered() {
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    records.fill_buffer(10).unwrap();
+    let poss =
+        |found: Vec<&GVcfRecord>| -> Vec<u32> { found.iter().map(|record| record.pos).collect() };
+
+    // Spans: (17330, 17330), (17331, 17331), (17333, 17336), (17334, 17337)
+    assert_eq!(
+        poss(records.query_buffered("20", 17331, 17333)),
+        vec![17331, 17333]
+    );
+    assert_eq!(
+        poss(records.query_buffered("20", 17337, 17400)),
+        vec![17334]
+    );
+    assert!(records.query_buffered("20", 17332, 17332).is_empty());
+    assert!(records.query_buffered("21", 17330, 17337).is_empty());
+    assert_eq!(records.count(), 4);
+}
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_timing_stats() {
+    let mut records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes());
+    assert_eq!(records.timing_stats().records, 0);
+    for record in records.by_ref() {
+        record.unwrap();
+    }
+    let stats = records.timing_stats();
+    assert_eq!(stats.records, 4);
+    assert!(stats.io_nanos > 0);
+    assert!(stats.parse_nanos > 0);
+}
+
+#[test]
+fn test_assert_sorted() {
+    let gvcf = "##fileformat=VCFv4.2
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA00001
+1\t10\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t30\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t20\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+1\t40\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+2\t5\t.\tA\tC,<NON_REF>\t.\t.\t.\tGT\t0/1
+";
+    let results: Vec<_> = GVcfRecordIterator::from_reader(gvcf.as_bytes())
+        .assert_sorted()
+        .collect();
+    assert_eq!(results.len(), 5);
+    assert!(results[0].is_ok() && results[1].is_ok());
+    match &results[2] {
+        Err(VcfParseError::UnsortedRecords {
+            chrom,
+            prev_pos,
+            cur_pos,
+        }) => assert_eq!((chrom.as_str(), *prev_pos, *cur_pos), ("1", 30, 20)),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(results[3..].iter().all(|result| result.is_ok()));
+
+    let n_records = GVcfRecordIterator::from_reader(SAMPLE_GVCF.as_bytes())
+        .assert_sorted()
+        .filter(|result| result.is_ok())
+        .count();
+    assert_eq!(n_records, 4);
+}
+
 #[test]
 fn test_gzip_reader() {
     let file = File::open("tests/data/sample.g.vcf.gz").expect("Problem opening test file");
@@ -94,6 +1098,25 @@ fn test_gzip_reader() {
     }
     assert_eq!(n_variants, 0);
 }
+#[test]
+fn test_multi_member_gzip_reader() {
+    let lines: Vec<&str> = SAMPLE_GVCF.lines().collect();
+    let (first_member, second_member) = lines.split_at(5);
+
+    let mut gzipped = Vec::new();
+    for member in [first_member, second_member] {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        for line in member {
+            writeln!(encoder, "{}", line).unwrap();
+        }
+        gzipped.extend(encoder.finish().unwrap());
+    }
+
+    let records = GVcfRecordIterator::from_gzip_reader(gzipped.as_slice());
+    let poss: Vec<u32> = records.map(|record| record.unwrap().pos).collect();
+    assert_eq!(poss, vec![17330, 17331, 17333, 17334]);
+}
+
 #[test]
 fn test_gzip_path() {
     let path = "tests/data/sample.g.vcf.gz";
@@ -114,9 +1137,78 @@ fn test_gzip_path() {
     assert_eq!(n_variants, 0);
 }
 
+#[test]
+fn test_gzip_path_magic_bytes() {
+    // The magic bytes are checked on the same handle used for decompression
+    let gzip_path = std::env::temp_dir().join(format!(
+        "gvcfparser_{}_gzip_path_magic_bytes.g.vcf.gz",
+        std::process::id()
+    ));
+    let mut encoder = GzEncoder::new(File::create(&gzip_path).unwrap(), Compression::default());
+    encoder.write_all(SAMPLE_GVCF.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+    let records = GVcfRecordIterator::from_gzip_path(&gzip_path).unwrap();
+    assert_eq!(records.filter(|record| record.is_ok()).count(), 4);
+
+    let plain_path = write_tmp_file("gzip_path_magic_bytes.g.vcf", SAMPLE_GVCF);
+    assert!(matches!(
+        GVcfRecordIterator::from_gzip_path(&plain_path),
+        Err(VcfParseError::VCFFileShouldBeGzipped)
+    ));
+    let empty_path = write_tmp_file("gzip_path_magic_bytes_empty.g.vcf", "");
+    assert!(matches!(
+        GVcfRecordIterator::from_gzip_path(&empty_path),
+        Err(VcfParseError::MagicByteError)
+    ));
+
+    for path in [gzip_path, plain_path, empty_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // The file is opened before its magic bytes are read, so a missing file is an Io error
+    assert!(matches!(
+        GVcfRecordIterator::from_gzip_path("missing_file.g.vcf.gz"),
+        Err(VcfParseError::Io { .. })
+    ));
+}
+
+#[test]
+fn test_open_vcf_reader() {
+    let plain_path = write_tmp_file("open_vcf_reader.g.vcf", SAMPLE_GVCF);
+    let paths = [
+        plain_path.clone(),
+        PathBuf::from("tests/data/sample.g.vcf.gz"),
+        PathBuf::from("tests/data/sample.g.vcf.bgz"),
+    ];
+    let expected_first_lines = ["##\n", "##fileformat=VCFv4.2\n", "##fileformat=VCFv4.2\n"];
+    for (path, expected_first_line) in paths.iter().zip(expected_first_lines) {
+        let mut reader = open_vcf_reader(path).expect("Problem opening test file");
+        let mut first_line = String::new();
+        reader.read_line(&mut first_line).unwrap();
+        assert_eq!(first_line, expected_first_line);
+    }
+    std::fs::remove_file(&plain_path).unwrap();
+}
+
+#[test]
+fn test_boxed_iterators() {
+    let reader = BufReader::new(SAMPLE_GVCF.as_bytes());
+    let iterators: Vec<BoxedGVcfIter> = vec![
+        GVcfRecordIterator::from_reader(reader).boxed(),
+        GVcfRecordIterator::from_gzip_path("tests/data/sample.g.vcf.gz")
+            .expect("Problem opening test file")
+            .boxed(),
+    ];
+    let n_variants: Vec<usize> = iterators
+        .into_iter()
+        .map(|iterator| iterator.filter(|record| record.is_ok()).count())
+        .collect();
+    assert_eq!(n_variants, vec![4, 0]);
+}
+
 #[test]
 fn test_performance() {
-    let path = "sample_files/sample.g.vcf.gz";
+    //let path = "sample_files/sample.g.vcf.gz";
     //let records = GVcfRecordIterator::from_gzip_path(path).expect("Problem opening test file");
     //println!("g.vcf.gzip");
 
@@ -151,24 +1243,183 @@ fn test_g_vcf_record() {
     let alleles = vec!["A".to_string(), "C".to_string()];
     let snp = GVcfRecord {
         chrom: "chr1".to_string(),
-        pos: pos,
-        alleles: alleles,
+        pos,
+        alleles,
     };
     assert!(matches!(snp.get_span(), Ok((10, 10))));
 
     let alleles = vec!["AT".to_string(), "A".to_string()];
     let snp = GVcfRecord {
         chrom: "chr1".to_string(),
-        pos: pos,
-        alleles: alleles,
+        pos,
+        alleles,
     };
     assert!(matches!(snp.get_span(), Ok((10, 11))));
 
     let alleles = vec!["A".to_string(), "ATT".to_string()];
     let snp = GVcfRecord {
         chrom: "chr1".to_string(),
-        pos: pos,
-        alleles: alleles,
+        pos,
+        alleles,
     };
     assert!(matches!(snp.get_span(), Ok((10, 12))));
 }
+
+#[test]
+fn test_g_vcf_record_bytes() {
+    let record = GVcfRecord {
+        chrom: "chr1".to_string(),
+        pos: 17333,
+        alleles: vec!["GTC".to_string(), "G".to_string(), "GTCT".to_string()],
+    };
+    let bytes = record.to_bytes();
+    let decoded = GVcfRecord::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.chrom, record.chrom);
+    assert_eq!(decoded.pos, record.pos);
+    assert_eq!(decoded.alleles, record.alleles);
+
+    let truncated = GVcfRecord::from_bytes(&bytes[..bytes.len() - 2]);
+    assert!(matches!(truncated, Err(VcfParseError::RuntimeError { .. })));
+
+    let no_alleles = GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 17333,
+        alleles: Vec::new(),
+    };
+    assert!(matches!(
+        GVcfRecord::from_bytes(&no_alleles.to_bytes()),
+        Err(VcfParseError::RuntimeError { .. })
+    ));
+}
+
+#[test]
+fn test_record_without_alleles() {
+    // A hand-built record without alleles does not make the accessors panic
+    let no_alleles = GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 17333,
+        alleles: Vec::new(),
+    };
+    assert!(!no_alleles.is_snp());
+    assert!(!no_alleles.is_indel());
+    assert!(!no_alleles.suspicious_ref_alt());
+    assert!(!no_alleles.has_symbolic_allele());
+    assert!(no_alleles.allele_spans().is_err());
+    assert_eq!(
+        record_to_json(&no_alleles),
+        "{\"chrom\":\"20\",\"pos\":17333,\"ref\":\"\",\"alt\":[]}"
+    );
+    assert_eq!(
+        no_alleles.ref_alt_strings(),
+        (String::new(), ".".to_string())
+    );
+}
+
+#[test]
+fn test_ref_alt_strings() {
+    let record = GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 17332,
+        alleles: vec!["T".to_string()],
+    };
+    assert_eq!(record.ref_alt_strings(), ("T".to_string(), ".".to_string()));
+
+    let record = GVcfRecord {
+        chrom: "20".to_string(),
+        pos: 17333,
+        alleles: vec!["GTC".to_string(), "G".to_string(), "GTCT".to_string()],
+    };
+    assert_eq!(
+        record.ref_alt_strings(),
+        ("GTC".to_string(), "G,GTCT".to_string())
+    );
+}
+
+#[test]
+fn test_breakend_mate() {
+    let record = GVcfRecord {
+        chrom: "chr2".to_string(),
+        pos: 321681,
+        alleles: vec![
+            "G".to_string(),
+            "G[chr13:123456[".to_string(),
+            "G]chr13:123456]".to_string(),
+            "]chr13:123456]G".to_string(),
+            "[chr13:123456[G".to_string(),
+            "T".to_string(),
+        ],
+    };
+    let orientations = [
+        BreakendOrientation::RightOfMateJoinedAfter,
+        BreakendOrientation::LeftOfMateReversedJoinedAfter,
+        BreakendOrientation::LeftOfMateJoinedBefore,
+        BreakendOrientation::RightOfMateReversedJoinedBefore,
+    ];
+    for (allele_idx, orientation) in (1..=4).zip(orientations) {
+        assert_eq!(
+            record.breakend_mate(allele_idx),
+            Some(Breakend {
+                mate_chrom: "chr13".to_string(),
+                mate_pos: 123456,
+                orientation,
+            })
+        );
+    }
+    assert_eq!(record.breakend_mate(0), None);
+    assert_eq!(record.breakend_mate(5), None);
+    assert_eq!(record.breakend_mate(6), None);
+}
+
+#[test]
+fn test_g_vcf_record_order() {
+    let snp = |chrom: &str, pos: u32, alt: &str| GVcfRecord {
+        chrom: chrom.to_string(),
+        pos,
+        alleles: vec!["A".to_string(), alt.to_string()],
+    };
+    let mut records = [
+        snp("chrX", 5, "C"),
+        snp("chr10", 1, "C"),
+        snp("chrMT", 1, "C"),
+        snp("chr2", 30, "G"),
+        snp("chr2", 30, "C"),
+        snp("chrY", 1, "C"),
+        snp("chr1", 100, "C"),
+        snp("chr2", 7, "C"),
+    ];
+    records.sort();
+    let sorted: Vec<(&str, u32, &str)> = records
+        .iter()
+        .map(|record| {
+            (
+                record.chrom.as_str(),
+                record.pos,
+                record.alleles[1].as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        sorted,
+        vec![
+            ("chr1", 100, "C"),
+            ("chr2", 7, "C"),
+            ("chr2", 30, "C"),
+            ("chr2", 30, "G"),
+            ("chr10", 1, "C"),
+            ("chrX", 5, "C"),
+            ("chrY", 1, "C"),
+            ("chrMT", 1, "C"),
+        ]
+    );
+
+    let chrom_order = ChromOrder::new(HashMap::from([
+        ("chrMT".to_string(), 0),
+        ("chr10".to_string(), 1),
+    ]));
+    records.sort_by(|record1, record2| chrom_order.compare(record1, record2));
+    let chroms: Vec<&str> = records.iter().map(|record| record.chrom.as_str()).collect();
+    assert_eq!(
+        chroms,
+        vec!["chrMT", "chr10", "chr1", "chr2", "chr2", "chr2", "chrX", "chrY"]
+    );
+}